@@ -0,0 +1,421 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    future::{self, Future},
+    io::{self, Write},
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpStream, ToSocketAddrs},
+    time,
+};
+
+use crate::{
+    common::OpcQuery, response, transport::MessageReader, Command, EncodeError, Parameter,
+    ProgramMessage, Query, TransportError,
+};
+
+/// Encodes a parameter and writes it to an asynchronous writer
+///
+/// Encoded bytes are written as soon as the writer accepts them, so a
+/// `StreamedBlock` is copied in chunks. Bytes are only buffered while the
+/// writer isn't ready, and written once encoding has finished.
+pub async fn write_parameter<P, W>(w: &mut W, param: P) -> Result<(), EncodeError>
+where
+    P: Parameter,
+    W: AsyncWrite + Unpin,
+{
+    let mut param = Some(param);
+    let pending = future::poll_fn(|cx| {
+        let mut writer = PollWriter {
+            w: &mut *w,
+            cx,
+            pending: Vec::new(),
+        };
+        let result = match param.take() {
+            Some(param) => param.encode(&mut writer),
+            None => Ok(()),
+        };
+        Poll::Ready(result.map(|()| writer.pending))
+    })
+    .await?;
+    w.write_all(&pending).await?;
+    Ok(())
+}
+
+/// Synchronous writer that writes to an asynchronous writer while it's ready
+///
+/// Bytes that can't be written without waiting are kept in `pending`.
+struct PollWriter<'a, 'b, W> {
+    w: &'a mut W,
+    cx: &'a mut Context<'b>,
+    pending: Vec<u8>,
+}
+
+impl<'a, 'b, W: AsyncWrite + Unpin> Write for PollWriter<'a, 'b, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        while !self.pending.is_empty() {
+            match poll_write(self.w, self.cx, &self.pending) {
+                Poll::Ready(result) => {
+                    self.pending.drain(..result?);
+                }
+                Poll::Pending => {
+                    self.pending.extend_from_slice(buf);
+                    return Ok(buf.len());
+                }
+            }
+        }
+        match poll_write(self.w, self.cx, buf) {
+            Poll::Ready(result) => result,
+            Poll::Pending => {
+                self.pending.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+        }
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes as much of the buffer as possible without waiting
+fn poll_write<W: AsyncWrite + Unpin>(
+    w: &mut W,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+) -> Poll<io::Result<usize>> {
+    match Pin::new(w).poll_write(cx, buf) {
+        Poll::Ready(Ok(0)) if !buf.is_empty() => Poll::Ready(Err(io::ErrorKind::WriteZero.into())),
+        result => result,
+    }
+}
+
+/// Finishes a program message and writes it to an asynchronous writer
+pub async fn write_message<W>(w: &mut W, msg: ProgramMessage<Vec<u8>>) -> Result<(), EncodeError>
+where
+    W: AsyncWrite + Unpin,
+{
+    let buf = msg.finish()?;
+    w.write_all(&buf).await?;
+    w.flush().await?;
+    Ok(())
+}
+
+/// Reads a newline terminated response message from an asynchronous byte stream
+///
+/// This is the asynchronous counterpart of `transport::read_terminated`.
+pub async fn read_terminated<R>(r: &mut R) -> io::Result<Vec<u8>>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut reader = MessageReader::default();
+    loop {
+        let buf = r.fill_buf().await?;
+        if buf.is_empty() {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (used, result) = reader.scan(buf);
+        r.consume(used);
+        if let Some(result) = result {
+            return result;
+        }
+    }
+}
+
+/// Discards input until nothing has been received for the given duration
+async fn discard_input<R>(r: &mut R, quiet: Duration) -> io::Result<()>
+where
+    R: AsyncBufRead + Unpin,
+{
+    loop {
+        let len = match time::timeout(quiet, r.fill_buf()).await {
+            Ok(buf) => buf?.len(),
+            Err(_) => return Ok(()),
+        };
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        r.consume(len);
+    }
+}
+
+/// Asynchronous client for instruments using newline terminated messages
+///
+/// Operations can be cancelled by dropping their futures. A response that
+/// was left unread by a cancelled or timed out operation would be mistaken
+/// for the response of the next query, so the client refuses further
+/// operations until `resync` has been called.
+#[derive(Debug)]
+pub struct AsyncClient<S> {
+    stream: BufReader<S>,
+    timeout: Option<Duration>,
+    interrupted: bool,
+}
+
+impl AsyncClient<TcpStream> {
+    /// Connects to a raw SCPI socket, using the timeout for connecting and for all subsequent operations
+    pub async fn connect<A: ToSocketAddrs>(
+        addr: A,
+        timeout: Duration,
+    ) -> io::Result<AsyncClient<TcpStream>> {
+        let stream = time::timeout(timeout, TcpStream::connect(addr))
+            .await
+            .map_err(|_| io::Error::from(io::ErrorKind::TimedOut))??;
+        stream.set_nodelay(true)?;
+        let mut client = AsyncClient::new(stream);
+        client.set_timeout(Some(timeout));
+        Ok(client)
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncClient<S> {
+    pub fn new(stream: S) -> AsyncClient<S> {
+        AsyncClient {
+            stream: BufReader::new(stream),
+            timeout: None,
+            interrupted: false,
+        }
+    }
+    /// Sets the time limit of every operation, or disables it if `None`
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeout = timeout;
+    }
+    /// Returns the underlying stream
+    pub fn stream(&mut self) -> &mut S {
+        self.stream.get_mut()
+    }
+    /// Returns true if an earlier operation was cancelled or timed out
+    pub fn is_interrupted(&self) -> bool {
+        self.interrupted
+    }
+    /// Returns a program message writer for the client
+    pub fn message(&self) -> ProgramMessage<Vec<u8>> {
+        ProgramMessage::new(Vec::new())
+    }
+    /// Sends a complete program message
+    pub async fn send_message(
+        &mut self,
+        msg: ProgramMessage<Vec<u8>>,
+    ) -> Result<(), TransportError> {
+        let message = msg.finish()?;
+        self.run(|stream| async move {
+            stream.write_all(&message).await?;
+            stream.flush().await
+        })
+        .await?;
+        Ok(())
+    }
+    /// Receives a complete response message
+    pub async fn read_message(&mut self) -> Result<Vec<u8>, TransportError> {
+        Ok(self.run(read_terminated).await?)
+    }
+    /// Sends a single command
+    pub async fn send<C: Command>(&mut self, command: C) -> Result<(), TransportError> {
+        let mut msg = self.message();
+        msg.command(command)?;
+        self.send_message(msg).await
+    }
+    /// Sends a single query and decodes its response
+    pub async fn query<Q: Query>(&mut self, query: Q) -> Result<Q::Response, TransportError> {
+        let mut msg = self.message();
+        msg.command(query)?;
+        let message = msg.finish()?;
+        let response = self
+            .run(|stream| async move {
+                stream.write_all(&message).await?;
+                stream.flush().await?;
+                read_terminated(stream).await
+            })
+            .await?;
+        Ok(response::parse(&response)?)
+    }
+    /// Discards responses left over from interrupted operations
+    ///
+    /// Input is discarded until nothing has been received for `quiet`, and
+    /// then `*OPC?` is sent and its reply read. A stale reply can't be
+    /// mistaken for the reply of `*OPC?`, as long as `quiet` is longer than
+    /// the instrument takes to answer the interrupted queries. The configured
+    /// timeout applies to both steps, so it must be longer than `quiet`.
+    pub async fn resync(&mut self, quiet: Duration) -> Result<(), TransportError> {
+        self.interrupted = false;
+        self.run(|stream| discard_input(stream, quiet)).await?;
+        self.query(OpcQuery).await?;
+        Ok(())
+    }
+    /// Runs an operation with the configured timeout
+    ///
+    /// The client is marked as interrupted until the operation has completed,
+    /// which also covers the future being dropped before completion.
+    async fn run<'s, F, Fut, T>(&'s mut self, op: F) -> io::Result<T>
+    where
+        F: FnOnce(&'s mut BufReader<S>) -> Fut,
+        Fut: Future<Output = io::Result<T>> + 's,
+    {
+        if self.interrupted {
+            return Err(io::Error::other(
+                "an earlier operation was interrupted, call resync first",
+            ));
+        }
+        self.interrupted = true;
+        let timeout = self.timeout;
+        let interrupted = &mut self.interrupted;
+        let fut = op(&mut self.stream);
+        let result = match timeout {
+            Some(timeout) => time::timeout(timeout, fut)
+                .await
+                .unwrap_or_else(|_| Err(io::ErrorKind::TimedOut.into())),
+            None => fut.await,
+        };
+        if result.is_ok() {
+            *interrupted = false;
+        }
+        result
+    }
+}
+
+#[tokio::test]
+async fn test_async_read_terminated() {
+    let mut input = &b"1.5,\"a\nb\",#14\n\n\n\n;#H1F\nNEXT"[..];
+    assert_eq!(
+        read_terminated(&mut input).await.unwrap(),
+        b"1.5,\"a\nb\",#14\n\n\n\n;#H1F\n"
+    );
+    assert_eq!(input, b"NEXT");
+    assert_eq!(
+        read_terminated(&mut input).await.unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[tokio::test]
+async fn test_async_write_parameter() {
+    use crate::Block;
+    let mut output = Vec::new();
+    write_parameter(&mut output, Block(b"abc")).await.unwrap();
+    let mut msg = ProgramMessage::new(Vec::new());
+    msg.command(crate::common::Rst).unwrap();
+    write_message(&mut output, msg).await.unwrap();
+    assert_eq!(output, b"#13abc*RST\n");
+}
+
+/// Asynchronous writer that accepts a few bytes at a time and is ready every other time
+#[cfg(test)]
+#[derive(Default)]
+struct SlowWriter {
+    data: Vec<u8>,
+    ready: bool,
+}
+
+#[cfg(test)]
+impl AsyncWrite for SlowWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        let len = buf.len().min(3);
+        self.data.extend_from_slice(&buf[..len]);
+        Poll::Ready(Ok(len))
+    }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[tokio::test]
+async fn test_async_write_streamed_block() {
+    use crate::StreamedBlock;
+    let data: Vec<u8> = (0..100).collect();
+    let mut output = SlowWriter::default();
+    write_parameter(&mut output, StreamedBlock::new(100, &data[..]))
+        .await
+        .unwrap();
+    assert_eq!(&output.data[..5], b"#3100");
+    assert_eq!(&output.data[5..], &data[..]);
+
+    let mut output = Vec::new();
+    let result = write_parameter(&mut output, StreamedBlock::new(12, &b"short"[..])).await;
+    assert!(matches!(
+        result,
+        Err(EncodeError::BlockLength {
+            expected: 12,
+            actual: 5
+        })
+    ));
+}
+
+#[tokio::test]
+async fn test_async_client_query() {
+    use crate::common::{IdnQuery, Rst};
+    let (client_stream, mut instrument) = tokio::io::duplex(64);
+    let mut client = AsyncClient::new(client_stream);
+    client.set_timeout(Some(Duration::from_secs(5)));
+    let server = tokio::spawn(async move {
+        let mut reader = BufReader::new(&mut instrument);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "*RST\n");
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "*IDN?\n");
+        instrument.write_all(b"ACME,X1,1234,1.0\n").await.unwrap();
+    });
+    client.send(Rst).await.unwrap();
+    let idn = client.query(IdnQuery).await.unwrap();
+    assert_eq!(idn.model, "X1");
+    server.await.unwrap();
+}
+
+#[tokio::test]
+async fn test_async_client_timeout() {
+    use crate::common::{IdnQuery, OpcQuery};
+    let (client_stream, mut instrument) = tokio::io::duplex(64);
+    let mut client = AsyncClient::new(client_stream);
+    client.set_timeout(Some(Duration::from_millis(20)));
+    match client.query(IdnQuery).await {
+        Err(TransportError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        result => panic!("unexpected result {:?}", result),
+    }
+    assert!(client.is_interrupted());
+    assert!(client.query(OpcQuery).await.is_err());
+
+    // The late response to *IDN? and a stale "1" are discarded while
+    // resynchronizing, so the reply to *OPC? and later replies line up
+    let server = tokio::spawn(async move {
+        instrument
+            .write_all(b"ACME,X1,1234,1.0\n1\n")
+            .await
+            .unwrap();
+        let mut reader = BufReader::new(&mut instrument);
+        let mut line = String::new();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "*IDN?\n");
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "*OPC?\n");
+        instrument.write_all(b"1\n").await.unwrap();
+        let mut reader = BufReader::new(&mut instrument);
+        line.clear();
+        reader.read_line(&mut line).await.unwrap();
+        assert_eq!(line, "*IDN?\n");
+        instrument.write_all(b"ACME,X2,5678,2.0\n").await.unwrap();
+    });
+    client.set_timeout(Some(Duration::from_secs(5)));
+    client.resync(Duration::from_millis(50)).await.unwrap();
+    assert!(!client.is_interrupted());
+    assert_eq!(client.query(IdnQuery).await.unwrap().model, "X2");
+    server.await.unwrap();
+}
@@ -0,0 +1,327 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    response::{FromResponse, Parser},
+    status::{StandardEventStatus, StatusByte},
+    Command, DecodeError, Header, Query,
+};
+
+macro_rules! common_command {
+    ($(#[$attr:meta])* $ty:ident, $mnemonic:expr) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub struct $ty;
+
+        impl Command for $ty {
+            type Params = ();
+            fn header(&self) -> Header<'static> {
+                Header::common($mnemonic)
+            }
+            fn params(self) -> Self::Params {}
+        }
+    };
+    ($(#[$attr:meta])* $ty:ident($param:ty), $mnemonic:expr) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub struct $ty(pub $param);
+
+        impl Command for $ty {
+            type Params = $param;
+            fn header(&self) -> Header<'static> {
+                Header::common($mnemonic)
+            }
+            fn params(self) -> Self::Params {
+                self.0
+            }
+        }
+    };
+}
+
+macro_rules! common_query {
+    ($(#[$attr:meta])* $ty:ident -> $response:ty, $mnemonic:expr) => {
+        $(#[$attr])*
+        #[derive(Copy, Clone, Debug, Eq, PartialEq)]
+        pub struct $ty;
+
+        impl Command for $ty {
+            type Params = ();
+            fn header(&self) -> Header<'static> {
+                Header::common($mnemonic).query()
+            }
+            fn params(self) -> Self::Params {}
+        }
+
+        impl Query for $ty {
+            type Response = $response;
+        }
+    };
+}
+
+common_command!(
+    /// Clear Status Command
+    ///
+    /// Reference: IEEE 488.2: 10.3 - *CLS
+    Cls,
+    "CLS"
+);
+common_command!(
+    /// Standard Event Status Enable Command
+    ///
+    /// Reference: IEEE 488.2: 10.10 - *ESE
+    Ese(StandardEventStatus),
+    "ESE"
+);
+common_query!(
+    /// Standard Event Status Enable Query
+    ///
+    /// Reference: IEEE 488.2: 10.11 - *ESE?
+    EseQuery -> StandardEventStatus,
+    "ESE"
+);
+common_query!(
+    /// Standard Event Status Register Query
+    ///
+    /// Reference: IEEE 488.2: 10.12 - *ESR?
+    EsrQuery -> StandardEventStatus,
+    "ESR"
+);
+common_query!(
+    /// Identification Query
+    ///
+    /// Reference: IEEE 488.2: 10.14 - *IDN?
+    IdnQuery -> Identification,
+    "IDN"
+);
+common_command!(
+    /// Operation Complete Command
+    ///
+    /// Reference: IEEE 488.2: 10.18 - *OPC
+    Opc,
+    "OPC"
+);
+common_query!(
+    /// Operation Complete Query
+    ///
+    /// Reference: IEEE 488.2: 10.19 - *OPC?
+    OpcQuery -> bool,
+    "OPC"
+);
+common_query!(
+    /// Option Identification Query
+    ///
+    /// Reference: IEEE 488.2: 10.20 - *OPT?
+    OptQuery -> Options,
+    "OPT"
+);
+common_command!(
+    /// Power-On Status Clear Command
+    ///
+    /// Reference: IEEE 488.2: 10.25 - *PSC
+    Psc(bool),
+    "PSC"
+);
+common_query!(
+    /// Power-On Status Clear Query
+    ///
+    /// Reference: IEEE 488.2: 10.26 - *PSC?
+    PscQuery -> bool,
+    "PSC"
+);
+common_command!(
+    /// Recall Command
+    ///
+    /// Reference: IEEE 488.2: 10.29 - *RCL
+    Rcl(u32),
+    "RCL"
+);
+common_command!(
+    /// Reset Command
+    ///
+    /// Reference: IEEE 488.2: 10.32 - *RST
+    Rst,
+    "RST"
+);
+common_command!(
+    /// Save Command
+    ///
+    /// Reference: IEEE 488.2: 10.33 - *SAV
+    Sav(u32),
+    "SAV"
+);
+common_command!(
+    /// Service Request Enable Command
+    ///
+    /// Reference: IEEE 488.2: 10.34 - *SRE
+    Sre(StatusByte),
+    "SRE"
+);
+common_query!(
+    /// Service Request Enable Query
+    ///
+    /// Reference: IEEE 488.2: 10.35 - *SRE?
+    SreQuery -> StatusByte,
+    "SRE"
+);
+common_query!(
+    /// Read Status Byte Query
+    ///
+    /// Reference: IEEE 488.2: 10.36 - *STB?
+    StbQuery -> StatusByte,
+    "STB"
+);
+common_command!(
+    /// Trigger Command
+    ///
+    /// Reference: IEEE 488.2: 10.37 - *TRG
+    Trg,
+    "TRG"
+);
+common_query!(
+    /// Self-Test Query, which returns zero if the test passed
+    ///
+    /// Reference: IEEE 488.2: 10.38 - *TST?
+    TstQuery -> i32,
+    "TST"
+);
+common_command!(
+    /// Wait-to-Continue Command
+    ///
+    /// Reference: IEEE 488.2: 10.39 - *WAI
+    Wai,
+    "WAI"
+);
+
+/// Response to the identification query
+///
+/// Reference: IEEE 488.2: 4.1.3.6 - *IDN? Response Fields
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Identification {
+    pub manufacturer: String,
+    pub model: String,
+    /// Serial number, or `0` if not available
+    pub serial_number: String,
+    /// Firmware level, or `0` if not available
+    pub firmware: String,
+}
+
+impl<'a> FromResponse<'a> for Identification {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let start = p.position();
+        let text = p.arbitrary_ascii()?;
+        let mut fields = text.split(',').map(|field| field.trim().to_owned());
+        match (fields.next(), fields.next(), fields.next(), fields.next()) {
+            (Some(manufacturer), Some(model), Some(serial_number), Some(firmware))
+                if fields.next().is_none() =>
+            {
+                Ok(Identification {
+                    manufacturer,
+                    model,
+                    serial_number,
+                    firmware,
+                })
+            }
+            _ => Err(DecodeError::Syntax {
+                expected: "identification fields",
+                position: start,
+            }),
+        }
+    }
+}
+
+/// Response to the option identification query
+///
+/// Reference: IEEE 488.2: 10.20 - *OPT?
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Options(pub Vec<String>);
+
+impl<'a> FromResponse<'a> for Options {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let text = p.arbitrary_ascii()?;
+        let options = text
+            .split(',')
+            .map(|option| option.trim().trim_matches('"').to_owned())
+            .filter(|option| !option.is_empty())
+            .collect();
+        Ok(Options(options))
+    }
+}
+
+#[cfg(test)]
+fn encode<C: Command>(command: C) -> String {
+    let mut msg = crate::ProgramMessage::new(Vec::new());
+    msg.command(command).unwrap();
+    String::from_utf8(msg.finish().unwrap()).unwrap()
+}
+
+#[test]
+fn test_common_commands() {
+    assert_eq!(encode(Cls), "*CLS\n");
+    assert_eq!(
+        encode(Ese(StandardEventStatus::CME | StandardEventStatus::QYE)),
+        "*ESE 36\n"
+    );
+    assert_eq!(encode(EsrQuery), "*ESR?\n");
+    assert_eq!(encode(Psc(true)), "*PSC 1\n");
+    assert_eq!(encode(Sav(3)), "*SAV 3\n");
+    assert_eq!(encode(OpcQuery), "*OPC?\n");
+}
+
+#[test]
+fn test_common_command_units() {
+    let mut msg = crate::ProgramMessage::new(Vec::new());
+    msg.command(Rst).unwrap();
+    msg.command(Cls).unwrap();
+    msg.command(OpcQuery).unwrap();
+    assert_eq!(msg.finish().unwrap(), b"*RST;*CLS;*OPC?\n");
+}
+
+#[test]
+fn test_common_query_responses() {
+    use crate::response::parse;
+    assert_eq!(parse::<<OpcQuery as Query>::Response>(b"1\n"), Ok(true));
+    assert_eq!(
+        parse::<<EsrQuery as Query>::Response>(b"+32\n"),
+        Ok(StandardEventStatus::CME)
+    );
+    assert_eq!(
+        parse::<<StbQuery as Query>::Response>(b"16\n"),
+        Ok(StatusByte::MAV)
+    );
+    assert_eq!(parse::<<TstQuery as Query>::Response>(b"0\n"), Ok(0));
+}
+
+#[test]
+fn test_identification_response() {
+    use crate::response::parse;
+    assert_eq!(
+        parse::<Identification>(b"Keysight Technologies,34465A,MY12345678,A.02.14-02.40\n"),
+        Ok(Identification {
+            manufacturer: "Keysight Technologies".to_owned(),
+            model: "34465A".to_owned(),
+            serial_number: "MY12345678".to_owned(),
+            firmware: "A.02.14-02.40".to_owned(),
+        })
+    );
+    assert_eq!(
+        parse::<Identification>(b"ACME,X1,0\n"),
+        Err(DecodeError::Syntax {
+            expected: "identification fields",
+            position: 0
+        })
+    );
+}
+
+#[test]
+fn test_options_response() {
+    use crate::response::parse;
+    assert_eq!(
+        parse::<Options>(b"\"MEM\",\"GPIB\"\n"),
+        Ok(Options(vec!["MEM".to_owned(), "GPIB".to_owned()]))
+    );
+    assert_eq!(
+        parse::<Options>(b"0,DIG\n"),
+        Ok(Options(vec!["0".to_owned(), "DIG".to_owned()]))
+    );
+}
@@ -0,0 +1,500 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::fmt;
+
+use crate::{
+    error_queue::{ErrorClass, ErrorQueue, ScpiError},
+    program::{self, FromProgram, Mnemonic, Parser, ProgramHeader, Unit},
+    ParseError, Response,
+};
+
+/// Node of a command pattern
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct PatternNode {
+    /// Mnemonic in `LONGform` notation
+    name: String,
+    /// Node that can be omitted (`[:LEVel]`)
+    optional: bool,
+    /// Node that accepts a numeric suffix (`SOURce#`)
+    suffix: bool,
+}
+
+/// Header pattern of a command in the command tree
+///
+/// Reference: SCPI 1999.0: 6.2 - Program Headers
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct Pattern {
+    common: bool,
+    nodes: Vec<PatternNode>,
+    query: bool,
+}
+
+impl Pattern {
+    pub(crate) fn parse(pattern: &str) -> Pattern {
+        let invalid = || panic!("invalid command pattern {:?}", pattern);
+        let mut rest = pattern;
+        let common = rest.starts_with('*');
+        if common {
+            rest = &rest[1..];
+        }
+        let query = rest.ends_with('?');
+        if query {
+            rest = &rest[..rest.len() - 1];
+        }
+        let mut nodes = Vec::new();
+        while !rest.is_empty() {
+            let optional = rest.starts_with('[');
+            let (segment, next) = if optional {
+                let end = rest.find(']').unwrap_or_else(invalid);
+                (&rest[1..end], &rest[end + 1..])
+            } else {
+                let end = rest[1..].find([':', '[']).map_or(rest.len(), |end| end + 1);
+                (&rest[..end], &rest[end..])
+            };
+            let segment = segment.strip_prefix(':').unwrap_or(segment);
+            let (name, suffix) = match segment.strip_suffix('#') {
+                Some(name) => (name, true),
+                None => (segment, false),
+            };
+            let valid = name.starts_with(|ch: char| ch.is_ascii_uppercase())
+                && name
+                    .chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_');
+            if !valid || (common && (optional || suffix)) {
+                invalid();
+            }
+            nodes.push(PatternNode {
+                name: name.to_owned(),
+                optional,
+                suffix,
+            });
+            rest = next;
+        }
+        if nodes.is_empty() || (common && nodes.len() > 1) {
+            invalid();
+        }
+        Pattern {
+            common,
+            nodes,
+            query,
+        }
+    }
+    /// Matches a header, returning the numeric suffixes of all suffixed nodes
+    ///
+    /// Omitted suffixes and optional nodes default to a suffix of 1.
+    pub(crate) fn matches(&self, header: &ProgramHeader) -> Option<Vec<u32>> {
+        if self.common != header.common || self.query != header.query {
+            return None;
+        }
+        let mut suffixes = Vec::new();
+        if match_nodes(&self.nodes, &header.nodes, &mut suffixes) {
+            Some(suffixes)
+        } else {
+            None
+        }
+    }
+}
+
+fn match_nodes(pattern: &[PatternNode], nodes: &[Mnemonic], suffixes: &mut Vec<u32>) -> bool {
+    let (node, rest) = match pattern.split_first() {
+        Some(split) => split,
+        None => return nodes.is_empty(),
+    };
+    let captured = suffixes.len();
+    if let Some((mnemonic, remaining)) = nodes.split_first() {
+        let suffix_ok = node.suffix || mnemonic.suffix.is_none();
+        if suffix_ok && mnemonic.matches(&node.name) {
+            if node.suffix {
+                suffixes.push(mnemonic.suffix.unwrap_or(1));
+            }
+            if match_nodes(rest, remaining, suffixes) {
+                return true;
+            }
+            suffixes.truncate(captured);
+        }
+    }
+    if node.optional {
+        if node.suffix {
+            suffixes.push(1);
+        }
+        if match_nodes(rest, nodes, suffixes) {
+            return true;
+        }
+        suffixes.truncate(captured);
+    }
+    false
+}
+
+type Handler<C> = Box<dyn Fn(&mut C, &mut Call<'_, '_>) -> Result<(), ScpiError> + Send>;
+
+struct Entry<C> {
+    pattern: Pattern,
+    params: bool,
+    handler: Handler<C>,
+}
+
+/// Invocation of a command handler
+pub struct Call<'a, 'b> {
+    unit: &'b Unit<'a>,
+    suffixes: Vec<u32>,
+    errors: &'b mut ErrorQueue,
+    response: Vec<u8>,
+}
+
+impl<'a, 'b> Call<'a, 'b> {
+    /// Returns the header of the message unit
+    pub fn header(&self) -> &ProgramHeader<'a> {
+        &self.unit.header
+    }
+    /// Returns the numeric suffix captured by the `index`th suffixed node of the pattern
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern has fewer suffixed nodes.
+    pub fn suffix(&self, index: usize) -> u32 {
+        self.suffixes[index]
+    }
+    /// Returns the numeric suffixes captured by all suffixed nodes of the pattern
+    pub fn suffixes(&self) -> &[u32] {
+        &self.suffixes
+    }
+    /// Parses all program data of the message unit
+    pub fn parse<T: FromProgram<'a>>(&mut self) -> Result<T, ParseError> {
+        self.unit.parse()
+    }
+    /// Returns a parser for the program data of the message unit
+    ///
+    /// The handler is responsible for checking that no data is left.
+    pub fn params(&mut self) -> Parser<'a> {
+        self.unit.params()
+    }
+    /// Appends response data to the response message unit of a query
+    pub fn respond<R: Response>(&mut self, value: R) -> Result<(), ScpiError> {
+        if !self.response.is_empty() {
+            self.response.push(b',');
+        }
+        value
+            .encode_response(&mut self.response)
+            .map_err(|_| ScpiError::new(-200, "Execution error"))
+    }
+    /// Returns the error/event queue of the instrument
+    pub fn errors(&mut self) -> &mut ErrorQueue {
+        self.errors
+    }
+}
+
+/// Command tree that dispatches received program messages to handlers
+///
+/// Commands are declared with header patterns written in the usual SCPI
+/// notation: `LONGform` mnemonics, optional nodes in brackets and `#` for
+/// nodes that accept a numeric suffix (e.g. `[SOURce#]:VOLTage[:LEVel]?`).
+/// Errors are recorded in the error/event queue, which can be read with the
+/// built-in `SYSTem:ERRor[:NEXT]?` and `SYSTem:ERRor:COUNt?` queries.
+///
+/// Reference: SCPI 1999.0: 6 - Program Headers, 21.8 - :ERRor Subsystem
+pub struct CommandTree<C> {
+    entries: Vec<Entry<C>>,
+    errors: ErrorQueue,
+}
+
+impl<C> fmt::Debug for CommandTree<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let patterns: Vec<_> = self.entries.iter().map(|entry| &entry.pattern).collect();
+        f.debug_struct("CommandTree")
+            .field("patterns", &patterns)
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl<C> Default for CommandTree<C> {
+    fn default() -> Self {
+        CommandTree::new()
+    }
+}
+
+impl<C> CommandTree<C> {
+    pub fn new() -> CommandTree<C> {
+        CommandTree {
+            entries: Vec::new(),
+            errors: ErrorQueue::new(),
+        }
+        .command("SYSTem:ERRor[:NEXT]?", |_, call| {
+            let error = call.errors().pop();
+            let text = match error.info {
+                Some(info) => format!("{};{}", error.message, info),
+                None => error.message,
+            };
+            call.respond((error.code, text.as_str()))
+        })
+        .command("SYSTem:ERRor:COUNt?", |_, call| {
+            let count = call.errors().len();
+            call.respond(count)
+        })
+    }
+    /// Adds a command or query handler that doesn't accept program data
+    ///
+    /// Program data is rejected with -108 before the handler runs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is not a valid header pattern.
+    pub fn command<F>(self, pattern: &str, handler: F) -> CommandTree<C>
+    where
+        F: Fn(&mut C, &mut Call<'_, '_>) -> Result<(), ScpiError> + Send + 'static,
+    {
+        self.entry(pattern, false, handler)
+    }
+    /// Adds a command or query handler that accepts program data
+    ///
+    /// The handler must parse all program data with `Call::parse`, or check
+    /// that no data is left with `Parser::finish`, before it has any side
+    /// effects.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is not a valid header pattern.
+    pub fn command_with_params<F>(self, pattern: &str, handler: F) -> CommandTree<C>
+    where
+        F: Fn(&mut C, &mut Call<'_, '_>) -> Result<(), ScpiError> + Send + 'static,
+    {
+        self.entry(pattern, true, handler)
+    }
+    fn entry<F>(mut self, pattern: &str, params: bool, handler: F) -> CommandTree<C>
+    where
+        F: Fn(&mut C, &mut Call<'_, '_>) -> Result<(), ScpiError> + Send + 'static,
+    {
+        self.entries.push(Entry {
+            pattern: Pattern::parse(pattern),
+            params,
+            handler: Box::new(handler),
+        });
+        self
+    }
+    /// Returns the error/event queue
+    pub fn errors(&mut self) -> &mut ErrorQueue {
+        &mut self.errors
+    }
+    /// Executes all message units of a program message, returning the response message
+    ///
+    /// Responses of queries are joined with `;` and terminated with a newline.
+    /// If the message contains no queries, the response is empty. Command
+    /// errors stop the execution of the rest of the message.
+    pub fn execute(&mut self, context: &mut C, message: &[u8]) -> Vec<u8> {
+        let mut responses = Vec::new();
+        for unit in program::units(message) {
+            let result = match unit {
+                Ok(unit) => self.execute_unit(context, &unit, &mut responses),
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                let class = err.class();
+                self.errors.push(err);
+                if class == ErrorClass::Command {
+                    break;
+                }
+            }
+        }
+        if responses.is_empty() {
+            return Vec::new();
+        }
+        let mut message = responses.join(&b';');
+        message.push(b'\n');
+        message
+    }
+    fn execute_unit(
+        &mut self,
+        context: &mut C,
+        unit: &Unit,
+        responses: &mut Vec<Vec<u8>>,
+    ) -> Result<(), ScpiError> {
+        // Later commands take precedence, so built-in commands can be replaced
+        let (entry, suffixes) = self
+            .entries
+            .iter()
+            .rev()
+            .find_map(|entry| {
+                entry
+                    .pattern
+                    .matches(&unit.header)
+                    .map(|suffixes| (entry, suffixes))
+            })
+            .ok_or_else(|| ScpiError::new(-113, "Undefined header"))?;
+        if !entry.params && unit.has_params() {
+            return Err(ParseError::ParameterNotAllowed {
+                position: unit.params().position(),
+            }
+            .into());
+        }
+        let mut call = Call {
+            unit,
+            suffixes,
+            errors: &mut self.errors,
+            response: Vec::new(),
+        };
+        (entry.handler)(context, &mut call)?;
+        if unit.header.query {
+            responses.push(call.response);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct Supply {
+    voltage: [f64; 2],
+    output: bool,
+    mode: &'static str,
+}
+
+#[cfg(test)]
+fn supply_tree() -> CommandTree<Supply> {
+    CommandTree::new()
+        .command("*IDN?", |_, call| {
+            call.respond(("ACME", crate::Discrete("PSU"), 1234, "1.0"))
+        })
+        .command("*RST", |supply: &mut Supply, _| {
+            *supply = Supply::default();
+            Ok(())
+        })
+        .command_with_params(
+            "[SOURce#]:VOLTage[:LEVel][:IMMediate][:AMPLitude]",
+            |supply: &mut Supply, call| {
+                let channel = call.suffix(0) as usize;
+                let voltage = call.parse::<f64>()?;
+                if !(0.0..=30.0).contains(&voltage) {
+                    return Err(ScpiError::new(-222, "Data out of range"));
+                }
+                let slot = supply
+                    .voltage
+                    .get_mut(channel - 1)
+                    .ok_or_else(|| ScpiError::new(-114, "Header suffix out of range"))?;
+                *slot = voltage;
+                Ok(())
+            },
+        )
+        .command(
+            "[SOURce#]:VOLTage[:LEVel][:IMMediate][:AMPLitude]?",
+            |supply: &mut Supply, call| {
+                let channel = call.suffix(0) as usize;
+                call.respond(supply.voltage[channel - 1])
+            },
+        )
+        .command_with_params("OUTPut[:STATe]", |supply: &mut Supply, call| {
+            supply.output = call.parse()?;
+            Ok(())
+        })
+        .command("OUTPut[:STATe]?", |supply: &mut Supply, call| {
+            call.respond(supply.output)
+        })
+        .command_with_params("OUTPut:MODE", |supply: &mut Supply, call| {
+            let mode = call.parse::<crate::Discrete>()?;
+            supply.mode = ["SERies", "PARallel"]
+                .iter()
+                .copied()
+                .find(|&mode_name| {
+                    Mnemonic {
+                        name: mode.0,
+                        suffix: None,
+                    }
+                    .matches(mode_name)
+                })
+                .ok_or_else(|| ScpiError::new(-224, "Illegal parameter value"))?;
+            Ok(())
+        })
+}
+
+#[test]
+fn test_pattern_parse() {
+    let pattern = Pattern::parse("[SOURce#]:VOLTage[:LEVel]?");
+    assert!(pattern.query);
+    assert!(!pattern.common);
+    assert_eq!(
+        pattern.nodes,
+        vec![
+            PatternNode {
+                name: "SOURce".to_owned(),
+                optional: true,
+                suffix: true
+            },
+            PatternNode {
+                name: "VOLTage".to_owned(),
+                optional: false,
+                suffix: false
+            },
+            PatternNode {
+                name: "LEVel".to_owned(),
+                optional: true,
+                suffix: false
+            },
+        ]
+    );
+    assert!(Pattern::parse("*IDN?").common);
+}
+
+#[test]
+#[should_panic(expected = "invalid command pattern")]
+fn test_pattern_parse_invalid() {
+    Pattern::parse("SOURce:[VOLTage");
+}
+
+#[test]
+fn test_dispatch() {
+    let mut tree = supply_tree();
+    let mut supply = Supply::default();
+    assert_eq!(tree.execute(&mut supply, b"VOLT 5\n"), b"");
+    assert_eq!(
+        tree.execute(&mut supply, b"SOUR2:VOLT:LEV:IMM:AMPL 12.5;:OUTP ON\n"),
+        b""
+    );
+    assert_eq!(supply.voltage, [5.0, 12.5]);
+    assert!(supply.output);
+    assert_eq!(
+        tree.execute(
+            &mut supply,
+            b"source2:voltage?;:VOLT?;*IDN?;:OUTPUT:STATE?\n"
+        ),
+        b"1.25E+1;5.0E+0;\"ACME\",PSU,1234,\"1.0\";1\n"
+    );
+    tree.execute(&mut supply, b"OUTP:MODE PAR\n");
+    assert_eq!(supply.mode, "PARallel");
+    assert!(tree.errors().is_empty());
+}
+
+#[test]
+fn test_dispatch_errors() {
+    let mut tree = supply_tree();
+    let mut supply = Supply::default();
+    let codes = |tree: &mut CommandTree<Supply>| -> Vec<i32> {
+        let codes = tree.errors().iter().map(|error| error.code).collect();
+        tree.errors().clear();
+        codes
+    };
+    tree.execute(&mut supply, b"VOLTS 5\n");
+    assert_eq!(codes(&mut tree), vec![-113]);
+    tree.execute(&mut supply, b"VOLT\n");
+    assert_eq!(codes(&mut tree), vec![-109]);
+    supply.voltage = [5.0, 0.0];
+    tree.execute(&mut supply, b"*RST 1\n");
+    assert_eq!(codes(&mut tree), vec![-108]);
+    assert_eq!(supply.voltage, [5.0, 0.0]);
+    tree.execute(&mut supply, b"OUTP:MODE SINGLE\n");
+    assert_eq!(codes(&mut tree), vec![-224]);
+    tree.execute(&mut supply, b"SOUR3:VOLT 1\n");
+    assert_eq!(codes(&mut tree), vec![-114]);
+
+    // Execution errors don't stop the rest of the message, but command errors do
+    tree.execute(&mut supply, b"VOLT 50;OUTP ON;VOLT 1,2;OUTP OFF\n");
+    assert_eq!(codes(&mut tree), vec![-222, -108]);
+    assert!(supply.output);
+    assert_eq!(supply.voltage, [5.0, 0.0]);
+
+    tree.execute(&mut supply, b"*IDN\n");
+    assert_eq!(
+        tree.execute(&mut supply, b"SYST:ERR:COUN?;NEXT?;NEXT?;:SYSTEM:ERROR?\n"),
+        b"1;-113,\"Undefined header\";0,\"No error\";0,\"No error\"\n"
+    );
+}
@@ -0,0 +1,267 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{error::Error, fmt, io};
+
+/// Error returned when a value can't be encoded as SCPI program data
+#[derive(Debug)]
+pub enum EncodeError {
+    /// Character that is not allowed in the encoded data element
+    InvalidCharacter {
+        ch: char,
+        /// Byte index of the character in the source string
+        index: usize,
+    },
+    /// Numeric value outside the range accepted by SCPI
+    ///
+    /// Reference: SCPI 1999.0: 7.2 - Decimal Numeric Program Data
+    OutOfRange(f64),
+    /// Mnemonic that doesn't follow the program mnemonic syntax
+    ///
+    /// Reference: IEEE 488.2: 7.6.1 - Program Mnemonic
+    InvalidMnemonic(String),
+    /// Channel in a channel list without any dimensions
+    ///
+    /// Reference: SCPI 1999.0: 8.3.2 - Channel List Parameter
+    EmptyChannel,
+    /// Streamed block source that ended before the declared length
+    BlockLength {
+        expected: u64,
+        actual: u64,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncodeError::InvalidCharacter { ch, index } => {
+                write!(f, "invalid character {:?} at index {}", ch, index)
+            }
+            EncodeError::OutOfRange(value) => write!(f, "numeric value {} is out of range", value),
+            EncodeError::InvalidMnemonic(mnemonic) => write!(f, "invalid mnemonic {:?}", mnemonic),
+            EncodeError::EmptyChannel => write!(f, "channel without any dimensions"),
+            EncodeError::BlockLength { expected, actual } => write!(
+                f,
+                "expected {} bytes of block data, but got {}",
+                expected, actual
+            ),
+            EncodeError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for EncodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EncodeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for EncodeError {
+    fn from(err: io::Error) -> Self {
+        EncodeError::Io(err)
+    }
+}
+
+/// Error returned when SCPI response data can't be decoded
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DecodeError {
+    /// Data that doesn't match the expected syntax
+    Syntax {
+        expected: &'static str,
+        /// Byte offset of the offending data in the response
+        position: usize,
+    },
+    /// Response ended in the middle of a data element
+    UnexpectedEnd { expected: &'static str },
+    /// Numeric value that doesn't fit in the target type
+    OutOfRange { position: usize },
+    /// Data left over after the expected response data was decoded
+    TrailingData { position: usize },
+    /// Block data whose length isn't a multiple of the sample size
+    BlockLength { len: usize, sample_size: usize },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Syntax { expected, position } => {
+                write!(f, "expected {} at position {}", expected, position)
+            }
+            DecodeError::UnexpectedEnd { expected } => {
+                write!(f, "expected {}, but response ended", expected)
+            }
+            DecodeError::OutOfRange { position } => {
+                write!(f, "numeric value at position {} is out of range", position)
+            }
+            DecodeError::TrailingData { position } => {
+                write!(f, "unexpected trailing data at position {}", position)
+            }
+            DecodeError::BlockLength { len, sample_size } => write!(
+                f,
+                "block length {} is not a multiple of the sample size {}",
+                len, sample_size
+            ),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Error returned when communicating with an instrument fails
+#[derive(Debug)]
+pub enum TransportError {
+    Io(io::Error),
+    Encode(EncodeError),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransportError::Io(err) => write!(f, "I/O error: {}", err),
+            TransportError::Encode(err) => write!(f, "encoding failed: {}", err),
+            TransportError::Decode(err) => write!(f, "decoding failed: {}", err),
+        }
+    }
+}
+
+impl Error for TransportError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TransportError::Io(err) => Some(err),
+            TransportError::Encode(err) => Some(err),
+            TransportError::Decode(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for TransportError {
+    fn from(err: io::Error) -> Self {
+        TransportError::Io(err)
+    }
+}
+
+impl From<EncodeError> for TransportError {
+    fn from(err: EncodeError) -> Self {
+        TransportError::Encode(err)
+    }
+}
+
+impl From<DecodeError> for TransportError {
+    fn from(err: DecodeError) -> Self {
+        TransportError::Decode(err)
+    }
+}
+
+/// Error returned when a program message received by an instrument can't be parsed
+///
+/// Every variant corresponds to a standard SCPI error/event number.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// Command header that doesn't follow the header syntax
+    ///
+    /// Reference: IEEE 488.2: 7.6 - <PROGRAM HEADER>
+    Header { position: usize },
+    /// Program mnemonic that is longer than 12 characters
+    MnemonicTooLong { position: usize },
+    /// Header suffix that doesn't fit in an integer
+    HeaderSuffix { position: usize },
+    /// Data that doesn't match the expected syntax
+    Syntax {
+        expected: &'static str,
+        /// Byte offset of the offending data in the program message
+        position: usize,
+    },
+    /// Data element of a different type than expected
+    DataType {
+        expected: &'static str,
+        position: usize,
+    },
+    /// Suffix after a numeric value that doesn't accept one
+    SuffixNotAllowed { position: usize },
+    /// Message unit that ended before all required parameters
+    MissingParameter { position: usize },
+    /// Parameters left over after the expected parameters were parsed
+    ParameterNotAllowed { position: usize },
+    /// Character data that isn't one of the accepted values
+    IllegalValue { position: usize },
+    /// Numeric value that doesn't fit in the target type
+    OutOfRange { position: usize },
+}
+
+impl ParseError {
+    /// Returns the standard error/event number of the error
+    ///
+    /// Reference: SCPI 1999.0: 21.8 - Error/Event numbers
+    pub fn code(&self) -> i32 {
+        match self {
+            ParseError::Header { .. } => -110,
+            ParseError::MnemonicTooLong { .. } => -112,
+            ParseError::HeaderSuffix { .. } => -114,
+            ParseError::Syntax { .. } => -102,
+            ParseError::DataType { .. } => -104,
+            ParseError::SuffixNotAllowed { .. } => -138,
+            ParseError::MissingParameter { .. } => -109,
+            ParseError::ParameterNotAllowed { .. } => -108,
+            ParseError::IllegalValue { .. } => -224,
+            ParseError::OutOfRange { .. } => -222,
+        }
+    }
+    /// Returns the byte offset of the offending data in the program message
+    pub fn position(&self) -> usize {
+        match *self {
+            ParseError::Header { position }
+            | ParseError::MnemonicTooLong { position }
+            | ParseError::HeaderSuffix { position }
+            | ParseError::Syntax { position, .. }
+            | ParseError::DataType { position, .. }
+            | ParseError::SuffixNotAllowed { position }
+            | ParseError::MissingParameter { position }
+            | ParseError::ParameterNotAllowed { position }
+            | ParseError::IllegalValue { position }
+            | ParseError::OutOfRange { position } => position,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::Header { position } => write!(f, "invalid header at position {}", position),
+            ParseError::MnemonicTooLong { position } => {
+                write!(f, "program mnemonic at position {} is too long", position)
+            }
+            ParseError::HeaderSuffix { position } => {
+                write!(f, "header suffix at position {} is out of range", position)
+            }
+            ParseError::Syntax { expected, position } => {
+                write!(f, "expected {} at position {}", expected, position)
+            }
+            ParseError::DataType { expected, position } => {
+                write!(f, "expected {} at position {}", expected, position)
+            }
+            ParseError::SuffixNotAllowed { position } => {
+                write!(f, "suffix at position {} is not allowed", position)
+            }
+            ParseError::MissingParameter { position } => {
+                write!(f, "missing parameter at position {}", position)
+            }
+            ParseError::ParameterNotAllowed { position } => {
+                write!(f, "unexpected parameter at position {}", position)
+            }
+            ParseError::IllegalValue { position } => {
+                write!(f, "illegal parameter value at position {}", position)
+            }
+            ParseError::OutOfRange { position } => {
+                write!(f, "numeric value at position {} is out of range", position)
+            }
+        }
+    }
+}
+
+impl Error for ParseError {}
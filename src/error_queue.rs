@@ -0,0 +1,490 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{collections::VecDeque, error::Error, fmt};
+
+use crate::{
+    response::{FromResponse, Parser},
+    status::StandardEventStatus,
+    Command, DecodeError, Header, ParseError, Query,
+};
+
+/// Class of an error/event number
+///
+/// Reference: SCPI 1999.0: 21.8 - Error/Event numbers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ErrorClass {
+    /// No error (`0`)
+    NoError,
+    /// Command error (`-100..=-199`), which sets CME in the event status register
+    Command,
+    /// Execution error (`-200..=-299`), which sets EXE in the event status register
+    Execution,
+    /// Device-specific error (`-300..=-399`), which sets DDE in the event status register
+    DeviceSpecific,
+    /// Query error (`-400..=-499`), which sets QYE in the event status register
+    Query,
+    /// Power on event (`-500..=-599`)
+    PowerOn,
+    /// User request event (`-600..=-699`)
+    UserRequest,
+    /// Request control event (`-700..=-799`)
+    RequestControl,
+    /// Operation complete event (`-800..=-899`)
+    OperationComplete,
+    /// Device-defined error (positive numbers)
+    DeviceDefined,
+    /// Reserved negative error number
+    Reserved,
+}
+
+impl ErrorClass {
+    pub fn from_code(code: i32) -> ErrorClass {
+        match code {
+            0 => ErrorClass::NoError,
+            -199..=-100 => ErrorClass::Command,
+            -299..=-200 => ErrorClass::Execution,
+            -399..=-300 => ErrorClass::DeviceSpecific,
+            -499..=-400 => ErrorClass::Query,
+            -599..=-500 => ErrorClass::PowerOn,
+            -699..=-600 => ErrorClass::UserRequest,
+            -799..=-700 => ErrorClass::RequestControl,
+            -899..=-800 => ErrorClass::OperationComplete,
+            code if code > 0 => ErrorClass::DeviceDefined,
+            _ => ErrorClass::Reserved,
+        }
+    }
+    /// Returns the standard event status bit set by errors/events of this class
+    ///
+    /// Reference: IEEE 488.2: 11.5.1.1 - Standard Event Status Register Bit Definitions
+    pub fn event_status(self) -> StandardEventStatus {
+        match self {
+            ErrorClass::Command => StandardEventStatus::CME,
+            ErrorClass::Execution => StandardEventStatus::EXE,
+            ErrorClass::DeviceSpecific => StandardEventStatus::DDE,
+            ErrorClass::Query => StandardEventStatus::QYE,
+            ErrorClass::PowerOn => StandardEventStatus::PON,
+            ErrorClass::UserRequest => StandardEventStatus::URQ,
+            ErrorClass::RequestControl => StandardEventStatus::RQC,
+            ErrorClass::OperationComplete => StandardEventStatus::OPC,
+            _ => StandardEventStatus::empty(),
+        }
+    }
+}
+
+/// Standard error/event numbers and their descriptions
+///
+/// Reference: SCPI 1999.0: 21.8 - Error/Event numbers
+const CATALOG: &[(i32, &str)] = &[
+    (0, "No error"),
+    (-100, "Command error"),
+    (-101, "Invalid character"),
+    (-102, "Syntax error"),
+    (-103, "Invalid separator"),
+    (-104, "Data type error"),
+    (-105, "GET not allowed"),
+    (-108, "Parameter not allowed"),
+    (-109, "Missing parameter"),
+    (-110, "Command header error"),
+    (-111, "Header separator error"),
+    (-112, "Program mnemonic too long"),
+    (-113, "Undefined header"),
+    (-114, "Header suffix out of range"),
+    (-115, "Unexpected number of parameters"),
+    (-120, "Numeric data error"),
+    (-121, "Invalid character in number"),
+    (-123, "Exponent too large"),
+    (-124, "Too many digits"),
+    (-128, "Numeric data not allowed"),
+    (-130, "Suffix error"),
+    (-131, "Invalid suffix"),
+    (-134, "Suffix too long"),
+    (-138, "Suffix not allowed"),
+    (-140, "Character data error"),
+    (-141, "Invalid character data"),
+    (-144, "Character data too long"),
+    (-148, "Character data not allowed"),
+    (-150, "String data error"),
+    (-151, "Invalid string data"),
+    (-158, "String data not allowed"),
+    (-160, "Block data error"),
+    (-161, "Invalid block data"),
+    (-168, "Block data not allowed"),
+    (-170, "Expression error"),
+    (-171, "Invalid expression"),
+    (-178, "Expression data not allowed"),
+    (-180, "Macro error"),
+    (-181, "Invalid outside macro definition"),
+    (-183, "Invalid inside macro definition"),
+    (-184, "Macro parameter error"),
+    (-200, "Execution error"),
+    (-201, "Invalid while in local"),
+    (-202, "Settings lost due to rtl"),
+    (-203, "Command protected"),
+    (-210, "Trigger error"),
+    (-211, "Trigger ignored"),
+    (-212, "Arm ignored"),
+    (-213, "Init ignored"),
+    (-214, "Trigger deadlock"),
+    (-215, "Arm deadlock"),
+    (-220, "Parameter error"),
+    (-221, "Settings conflict"),
+    (-222, "Data out of range"),
+    (-223, "Too much data"),
+    (-224, "Illegal parameter value"),
+    (-225, "Out of memory"),
+    (-226, "Lists not same length"),
+    (-230, "Data corrupt or stale"),
+    (-231, "Data questionable"),
+    (-232, "Invalid format"),
+    (-233, "Invalid version"),
+    (-240, "Hardware error"),
+    (-241, "Hardware missing"),
+    (-250, "Mass storage error"),
+    (-251, "Missing mass storage"),
+    (-252, "Missing media"),
+    (-253, "Corrupt media"),
+    (-254, "Media full"),
+    (-255, "Directory full"),
+    (-256, "File name not found"),
+    (-257, "File name error"),
+    (-258, "Media protected"),
+    (-260, "Expression error"),
+    (-261, "Math error in expression"),
+    (-270, "Macro error"),
+    (-271, "Macro syntax error"),
+    (-272, "Macro execution error"),
+    (-273, "Illegal macro label"),
+    (-274, "Macro parameter error"),
+    (-275, "Macro definition too long"),
+    (-276, "Macro recursion error"),
+    (-277, "Macro redefinition not allowed"),
+    (-278, "Macro header not found"),
+    (-280, "Program error"),
+    (-281, "Cannot create program"),
+    (-282, "Illegal program name"),
+    (-283, "Illegal variable name"),
+    (-284, "Program currently running"),
+    (-285, "Program syntax error"),
+    (-286, "Program runtime error"),
+    (-290, "Memory use error"),
+    (-291, "Out of memory"),
+    (-292, "Referenced name does not exist"),
+    (-293, "Referenced name already exists"),
+    (-294, "Incompatible type"),
+    (-300, "Device-specific error"),
+    (-310, "System error"),
+    (-311, "Memory error"),
+    (-312, "PUD memory lost"),
+    (-313, "Calibration memory lost"),
+    (-314, "Save/recall memory lost"),
+    (-315, "Configuration memory lost"),
+    (-320, "Storage fault"),
+    (-321, "Out of memory"),
+    (-330, "Self-test failed"),
+    (-340, "Calibration failed"),
+    (-350, "Queue overflow"),
+    (-360, "Communication error"),
+    (-361, "Parity error in program message"),
+    (-362, "Framing error in program message"),
+    (-363, "Input buffer overrun"),
+    (-365, "Time out error"),
+    (-400, "Query error"),
+    (-410, "Query INTERRUPTED"),
+    (-420, "Query UNTERMINATED"),
+    (-430, "Query DEADLOCKED"),
+    (-440, "Query UNTERMINATED after indefinite response"),
+    (-500, "Power on"),
+    (-600, "User request"),
+    (-700, "Request control"),
+    (-800, "Operation complete"),
+];
+
+/// Returns the standard description of an error/event number
+pub fn standard_message(code: i32) -> Option<&'static str> {
+    CATALOG
+        .iter()
+        .find(|&&(catalog_code, _)| catalog_code == code)
+        .map(|&(_, message)| message)
+}
+
+/// Entry of the error/event queue
+///
+/// Reference: SCPI 1999.0: 21.8 - :ERRor Subsystem
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScpiError {
+    pub code: i32,
+    pub message: String,
+    /// Device-dependent information appended to the message after `;`
+    pub info: Option<String>,
+}
+
+impl ScpiError {
+    pub fn new(code: i32, message: &str) -> ScpiError {
+        ScpiError {
+            code,
+            message: message.to_owned(),
+            info: None,
+        }
+    }
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::from_code(self.code)
+    }
+    /// Returns true if the entry means the queue is empty
+    pub fn is_no_error(&self) -> bool {
+        self.code == 0
+    }
+}
+
+impl fmt::Display for ScpiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}, {}", self.code, self.message)?;
+        if let Some(info) = &self.info {
+            write!(f, " ({})", info)?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for ScpiError {}
+
+impl<'a> FromResponse<'a> for ScpiError {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let (code, text): (i32, String) = p.parse()?;
+        let (message, info) = match text.find(';') {
+            Some(idx) => (text[..idx].to_owned(), Some(text[idx + 1..].to_owned())),
+            None => (text, None),
+        };
+        Ok(ScpiError {
+            code,
+            message,
+            info,
+        })
+    }
+}
+
+impl From<ParseError> for ScpiError {
+    fn from(err: ParseError) -> Self {
+        let code = err.code();
+        ScpiError::new(code, standard_message(code).unwrap_or("Command error"))
+    }
+}
+
+#[test]
+fn test_scpi_error_from_parse_error() {
+    assert_eq!(
+        ScpiError::from(ParseError::MissingParameter { position: 4 }),
+        ScpiError::new(-109, "Missing parameter")
+    );
+    assert_eq!(
+        ScpiError::from(ParseError::IllegalValue { position: 4 }).class(),
+        ErrorClass::Execution
+    );
+}
+
+/// Error/event queue kept by an instrument
+///
+/// When an error arrives while the queue is full, the most recent entry is
+/// replaced with `-350, "Queue overflow"` and the new error is discarded. The standard
+/// event status bits of all pushed errors are recorded, including discarded
+/// ones, until they are taken with `take_events`.
+///
+/// Reference: SCPI 1999.0: 21.8.1 - Error/Event Queue
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ErrorQueue {
+    entries: VecDeque<ScpiError>,
+    capacity: usize,
+    events: StandardEventStatus,
+}
+
+impl Default for ErrorQueue {
+    fn default() -> Self {
+        ErrorQueue::new()
+    }
+}
+
+impl ErrorQueue {
+    /// Queue length used by default
+    pub const DEFAULT_CAPACITY: usize = 16;
+
+    pub fn new() -> ErrorQueue {
+        ErrorQueue::with_capacity(ErrorQueue::DEFAULT_CAPACITY)
+    }
+    /// Creates a queue that holds at most `capacity` entries
+    pub fn with_capacity(capacity: usize) -> ErrorQueue {
+        ErrorQueue {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+            events: StandardEventStatus::empty(),
+        }
+    }
+    /// Appends an error, or records an overflow if the queue is full
+    pub fn push(&mut self, error: ScpiError) {
+        self.events |= error.class().event_status();
+        if self.entries.len() < self.capacity {
+            self.entries.push_back(error);
+        } else if let Some(last) = self.entries.back_mut() {
+            if last.code != -350 {
+                *last = ScpiError::new(-350, "Queue overflow");
+            }
+        }
+    }
+    /// Removes and returns the oldest error, or `0, "No error"` if the queue is empty
+    pub fn pop(&mut self) -> ScpiError {
+        self.entries
+            .pop_front()
+            .unwrap_or_else(|| ScpiError::new(0, "No error"))
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &ScpiError> {
+        self.entries.iter()
+    }
+    /// Returns and clears the standard event status bits recorded since the last call
+    pub fn take_events(&mut self) -> StandardEventStatus {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[test]
+fn test_error_queue() {
+    let mut queue = ErrorQueue::with_capacity(3);
+    assert_eq!(queue.pop(), ScpiError::new(0, "No error"));
+    queue.push(ScpiError::new(-113, "Undefined header"));
+    queue.push(ScpiError::new(-109, "Missing parameter"));
+    queue.push(ScpiError::new(-224, "Illegal parameter value"));
+    assert_eq!(queue.len(), 3);
+    assert_eq!(
+        queue.iter().map(|error| error.code).collect::<Vec<_>>(),
+        vec![-113, -109, -224]
+    );
+    queue.push(ScpiError::new(-108, "Parameter not allowed"));
+    queue.push(ScpiError::new(-410, "Query INTERRUPTED"));
+    assert_eq!(queue.len(), 3);
+    assert_eq!(queue.pop().code, -113);
+    assert_eq!(queue.pop().code, -109);
+    assert_eq!(queue.pop(), ScpiError::new(-350, "Queue overflow"));
+    assert!(queue.is_empty());
+    assert_eq!(
+        queue.take_events(),
+        StandardEventStatus::CME | StandardEventStatus::EXE | StandardEventStatus::QYE
+    );
+    assert!(queue.take_events().is_empty());
+}
+
+/// Query that reads and removes the oldest entry of the error/event queue
+///
+/// Reference: SCPI 1999.0: 21.8.8 - [:NEXT]?
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct NextError;
+
+impl Command for NextError {
+    type Params = ();
+    fn header(&self) -> Header<'static> {
+        Header::new()
+            .node("SYSTem")
+            .node("ERRor")
+            .optional("NEXT")
+            .query()
+    }
+    fn params(self) -> Self::Params {}
+}
+
+impl Query for NextError {
+    type Response = ScpiError;
+}
+
+/// Reads errors using the given function until the queue is empty
+///
+/// The function is expected to send `NextError` and decode the response. At
+/// most `limit` errors are read, so an instrument that never reports
+/// `0, "No error"` can't keep the caller reading forever. If the limit is
+/// reached, the errors read so far are returned.
+pub fn drain<F, E>(limit: usize, mut next: F) -> Result<Vec<ScpiError>, E>
+where
+    F: FnMut() -> Result<ScpiError, E>,
+{
+    let mut errors = Vec::new();
+    while errors.len() < limit {
+        let error = next()?;
+        if error.is_no_error() {
+            break;
+        }
+        errors.push(error);
+    }
+    Ok(errors)
+}
+
+#[test]
+fn test_scpi_error_response() {
+    use crate::response::parse;
+    assert_eq!(
+        parse::<ScpiError>(b"-113,\"Undefined header\"\n"),
+        Ok(ScpiError::new(-113, "Undefined header"))
+    );
+    assert_eq!(
+        parse::<ScpiError>(b"+0,\"No error\"\n"),
+        Ok(ScpiError::new(0, "No error"))
+    );
+    let error = parse::<ScpiError>(b"-222,\"Data out of range;VOLT 50\"\n").unwrap();
+    assert_eq!(error.message, "Data out of range");
+    assert_eq!(error.info.as_deref(), Some("VOLT 50"));
+    assert_eq!(error.class(), ErrorClass::Execution);
+    assert_eq!(error.to_string(), "-222, Data out of range (VOLT 50)");
+}
+
+#[test]
+fn test_error_catalog() {
+    assert_eq!(standard_message(-113), Some("Undefined header"));
+    assert_eq!(standard_message(-350), Some("Queue overflow"));
+    assert_eq!(standard_message(-999), None);
+    assert_eq!(ErrorClass::from_code(-410), ErrorClass::Query);
+    assert_eq!(ErrorClass::from_code(-350), ErrorClass::DeviceSpecific);
+    assert_eq!(ErrorClass::from_code(12), ErrorClass::DeviceDefined);
+    assert_eq!(ErrorClass::from_code(-900), ErrorClass::Reserved);
+}
+
+#[test]
+fn test_next_error_query() {
+    let mut msg = crate::ProgramMessage::new(Vec::new());
+    msg.command(NextError).unwrap();
+    assert_eq!(msg.finish().unwrap(), b"SYST:ERR?\n");
+}
+
+#[test]
+fn test_drain() {
+    let mut responses = vec![
+        &b"0,\"No error\"\n"[..],
+        &b"-410,\"Query INTERRUPTED\"\n"[..],
+        &b"-113,\"Undefined header\"\n"[..],
+    ];
+    let errors = drain(16, || {
+        crate::response::parse::<ScpiError>(responses.pop().unwrap())
+    })
+    .unwrap();
+    assert_eq!(
+        errors,
+        vec![
+            ScpiError::new(-113, "Undefined header"),
+            ScpiError::new(-410, "Query INTERRUPTED"),
+        ]
+    );
+    assert!(responses.is_empty());
+
+    let mut calls = 0;
+    let errors = drain(3, || -> Result<_, DecodeError> {
+        calls += 1;
+        Ok(ScpiError::new(-350, "Queue overflow"))
+    })
+    .unwrap();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(calls, 3);
+}
@@ -0,0 +1,255 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io::Write;
+
+use crate::EncodeError;
+
+/// Mnemonic form used when encoding headers
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum HeaderForm {
+    /// Only the uppercase part of each mnemonic (e.g. `VOLT`), omitting optional nodes
+    #[default]
+    Short,
+    /// Complete mnemonics (e.g. `VOLTAGE`), including optional nodes
+    Long,
+}
+
+/// Node of a compound header
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+struct Node<'a> {
+    mnemonic: &'a str,
+    suffix: Option<u32>,
+    optional: bool,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Kind<'a> {
+    Common(&'a str),
+    Compound(Vec<Node<'a>>),
+}
+
+/// SCPI command or query program header
+///
+/// Mnemonics are given in the SCPI notation where the uppercase part is the
+/// short form and the complete mnemonic is the long form (e.g. `VOLTage`).
+///
+/// Reference: IEEE 488.2: 7.6 - <PROGRAM HEADER>, SCPI 1999.0: 6.2 - Command Headers
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Header<'a> {
+    kind: Kind<'a>,
+    query: bool,
+}
+
+impl<'a> Header<'a> {
+    /// Creates a compound header with no nodes
+    pub fn new() -> Header<'a> {
+        Header {
+            kind: Kind::Compound(Vec::new()),
+            query: false,
+        }
+    }
+    /// Creates a common command header (e.g. `*RST`) without the leading asterisk
+    ///
+    /// Reference: IEEE 488.2: 7.6.1.2 - <COMMON COMMAND PROGRAM HEADER>
+    pub fn common(mnemonic: &'a str) -> Header<'a> {
+        Header {
+            kind: Kind::Common(mnemonic),
+            query: false,
+        }
+    }
+    /// Appends a node to a compound header
+    pub fn node(self, mnemonic: &'a str) -> Header<'a> {
+        self.push(Node {
+            mnemonic,
+            suffix: None,
+            optional: false,
+        })
+    }
+    /// Appends an optional node, which is only encoded in the long form
+    pub fn optional(self, mnemonic: &'a str) -> Header<'a> {
+        self.push(Node {
+            mnemonic,
+            suffix: None,
+            optional: true,
+        })
+    }
+    /// Sets the numeric suffix of the last node
+    ///
+    /// Reference: SCPI 1999.0: 6.2.5 - Numeric Suffixes
+    pub fn suffix(mut self, suffix: u32) -> Header<'a> {
+        if let Kind::Compound(nodes) = &mut self.kind {
+            if let Some(node) = nodes.last_mut() {
+                node.suffix = Some(suffix);
+            }
+        }
+        self
+    }
+    /// Marks the header as a query by appending `?`
+    pub fn query(mut self) -> Header<'a> {
+        self.query = true;
+        self
+    }
+    /// Returns true if the header is a query
+    pub fn is_query(&self) -> bool {
+        self.query
+    }
+    /// Encodes the header using the given mnemonic form
+    pub fn encode<W: Write>(&self, form: HeaderForm, w: &mut W) -> Result<(), EncodeError> {
+        match &self.kind {
+            Kind::Common(mnemonic) => {
+                check_mnemonic(mnemonic)?;
+                w.write_all(b"*")?;
+                w.write_all(mnemonic.to_ascii_uppercase().as_bytes())?;
+            }
+            Kind::Compound(nodes) => {
+                if nodes.is_empty() {
+                    return Err(EncodeError::InvalidMnemonic(String::new()));
+                }
+                for node in nodes {
+                    check_mnemonic(node.mnemonic)?;
+                    if node.suffix == Some(0) {
+                        return Err(EncodeError::InvalidMnemonic(format!("{}0", node.mnemonic)));
+                    }
+                }
+                let nodes = nodes.iter().filter(|node| match form {
+                    HeaderForm::Short => !node.optional,
+                    HeaderForm::Long => true,
+                });
+                for (idx, node) in nodes.enumerate() {
+                    if idx > 0 {
+                        w.write_all(b":")?;
+                    }
+                    let text = match form {
+                        HeaderForm::Short => short_form(node.mnemonic),
+                        HeaderForm::Long => node.mnemonic,
+                    };
+                    w.write_all(text.to_ascii_uppercase().as_bytes())?;
+                    if let Some(suffix) = node.suffix {
+                        write!(w, "{}", suffix)?;
+                    }
+                }
+            }
+        }
+        if self.query {
+            w.write_all(b"?")?;
+        }
+        Ok(())
+    }
+    fn push(mut self, node: Node<'a>) -> Header<'a> {
+        if let Kind::Compound(nodes) = &mut self.kind {
+            nodes.push(node);
+        }
+        self
+    }
+}
+
+impl<'a> Default for Header<'a> {
+    fn default() -> Header<'a> {
+        Header::new()
+    }
+}
+
+/// Returns the uppercase part of a mnemonic
+fn short_form(mnemonic: &str) -> &str {
+    let end = mnemonic
+        .find(|ch: char| ch.is_ascii_lowercase())
+        .unwrap_or(mnemonic.len());
+    &mnemonic[..end]
+}
+
+/// Checks that the mnemonic follows the program mnemonic syntax and the SCPI short/long notation
+///
+/// Reference: IEEE 488.2: 7.6.1 - <program mnemonic>
+fn check_mnemonic(mnemonic: &str) -> Result<(), EncodeError> {
+    let invalid = || EncodeError::InvalidMnemonic(mnemonic.to_owned());
+    let mut bytes = mnemonic.bytes();
+    if !bytes.next().is_some_and(|b| b.is_ascii_uppercase()) {
+        return Err(invalid());
+    }
+    if !bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_') || mnemonic.len() > 12 {
+        return Err(invalid());
+    }
+    // Lowercase characters are only allowed after the short form
+    let short = short_form(mnemonic);
+    if mnemonic[short.len()..]
+        .bytes()
+        .any(|b| !b.is_ascii_lowercase())
+    {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+fn encode(header: &Header, form: HeaderForm) -> Result<String, EncodeError> {
+    let mut buf = Vec::new();
+    header.encode(form, &mut buf)?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[test]
+fn test_common_header() {
+    let header = Header::common("RST");
+    assert_eq!(encode(&header, HeaderForm::Short).unwrap(), "*RST");
+    let header = Header::common("IDN").query();
+    assert!(header.is_query());
+    assert_eq!(encode(&header, HeaderForm::Long).unwrap(), "*IDN?");
+}
+
+#[test]
+fn test_compound_header() {
+    let header = Header::new()
+        .node("SOURce")
+        .suffix(1)
+        .node("VOLTage")
+        .optional("LEVel")
+        .optional("IMMediate")
+        .node("AMPLitude");
+    assert_eq!(
+        encode(&header, HeaderForm::Short).unwrap(),
+        "SOUR1:VOLT:AMPL"
+    );
+    assert_eq!(
+        encode(&header, HeaderForm::Long).unwrap(),
+        "SOURCE1:VOLTAGE:LEVEL:IMMEDIATE:AMPLITUDE"
+    );
+    let header = Header::new()
+        .node("MEASure")
+        .node("VOLTage")
+        .node("DC")
+        .query();
+    assert_eq!(encode(&header, HeaderForm::Short).unwrap(), "MEAS:VOLT:DC?");
+}
+
+#[test]
+fn test_header_invalid_mnemonic() {
+    for &invalid in &[
+        "",
+        "volt",
+        "VOLTage1",
+        "VOLt_AGE",
+        "1ST",
+        "SOUR:VOLT",
+        "VERYLONGMnemonic",
+    ] {
+        let header = Header::new().node(invalid);
+        assert!(matches!(
+            encode(&header, HeaderForm::Short),
+            Err(EncodeError::InvalidMnemonic(ref m)) if m == invalid
+        ));
+    }
+    assert!(matches!(
+        encode(&Header::new(), HeaderForm::Short),
+        Err(EncodeError::InvalidMnemonic(_))
+    ));
+    assert!(matches!(
+        encode(&Header::new().node("OUTPut").suffix(0), HeaderForm::Short),
+        Err(EncodeError::InvalidMnemonic(ref m)) if m == "OUTPut0"
+    ));
+    assert!(matches!(
+        encode(&Header::common("R ST"), HeaderForm::Short),
+        Err(EncodeError::InvalidMnemonic(_))
+    ));
+}
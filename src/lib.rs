@@ -2,17 +2,48 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+pub use crate::error::{DecodeError, EncodeError, ParseError, TransportError};
+pub use crate::header::{Header, HeaderForm};
+pub use crate::message::{Command, ProgramMessage, Query, Terminator};
 pub use crate::param::Parameter;
+pub use crate::response_data::Response;
 use std::fmt;
 
+/// Asynchronous encoding and client using tokio
+#[cfg(feature = "tokio")]
+pub mod asynchronous;
+/// IEEE 488.2 common commands
+pub mod common;
+/// Command trees for building instruments
+pub mod dispatch;
+mod error;
+/// SCPI error/event queue
+pub mod error_queue;
+mod header;
+mod message;
+/// Scriptable mock instrument for integration tests
+pub mod mock;
 mod param;
+/// Parsing of program messages received by instruments
+pub mod program;
+/// Decoding of response data sent by instruments
+pub mod response;
+mod response_data;
+/// Status reporting registers
+pub mod status;
+/// Connections to instruments
+pub mod transport;
+/// Numeric values with unit suffixes
+pub mod unit;
+/// Binary sample data transferred as arbitrary blocks
+pub mod waveform;
 
 /// Discrete SCPI parameter
 ///
 /// Reference: IEEE 488.2
 #[repr(transparent)]
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub struct Discrete(pub &'static str);
+pub struct Discrete<'a>(pub &'a str);
 
 /// An arbitrary block of bytes
 ///
@@ -21,6 +52,145 @@ pub struct Discrete(pub &'static str);
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Block<'a>(pub &'a [u8]);
 
+/// An arbitrary block of known length streamed from a reader
+///
+/// Exactly `len` bytes are read from the source while encoding.
+///
+/// Reference: IEEE 488.2: 7.7.6.2 - <DEFINITE LENGTH ARBITRARY BLOCK PROGRAM DATA>
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StreamedBlock<R> {
+    pub len: u64,
+    pub source: R,
+}
+
+impl<R> StreamedBlock<R> {
+    pub fn new(len: u64, source: R) -> StreamedBlock<R> {
+        StreamedBlock { len, source }
+    }
+}
+
+/// An indefinite length arbitrary block streamed from a reader until it's exhausted
+///
+/// The block extends to the end of the program message, so it must be the
+/// last parameter of the last message unit, and the message must be
+/// terminated with `Terminator::NewlineEnd`.
+///
+/// Reference: IEEE 488.2: 7.7.6.3 - <INDEFINITE LENGTH ARBITRARY BLOCK PROGRAM DATA>
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IndefiniteBlock<R>(pub R);
+
+/// Channel address with one or more dimensions (e.g. `1!2`)
+///
+/// Reference: SCPI 1999.0: 8.3.2 - Channel List Parameter
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Channel(pub Vec<u32>);
+
+impl From<u32> for Channel {
+    fn from(channel: u32) -> Channel {
+        Channel(vec![channel])
+    }
+}
+
+/// Entry of a channel list
+///
+/// Reference: SCPI 1999.0: 8.3.2 - Channel List Parameter
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChannelEntry {
+    /// Single channel (e.g. `1` or `1!2`)
+    Channel(Channel),
+    /// Inclusive range of channels (e.g. `3:5` or `3!4:3!8`)
+    Range(Channel, Channel),
+    /// Entries prefixed with a module specifier (e.g. `CARD1(1,3:5)`)
+    Module(String, Vec<ChannelEntry>),
+}
+
+/// List of channels (e.g. `(@1,3:5)`)
+///
+/// Reference: SCPI 1999.0: 8.3.2 - Channel List Parameter
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ChannelList(pub Vec<ChannelEntry>);
+
+impl ChannelList {
+    pub fn new() -> ChannelList {
+        ChannelList(Vec::new())
+    }
+    /// Appends a single channel
+    pub fn channel<C: Into<Channel>>(mut self, channel: C) -> ChannelList {
+        self.0.push(ChannelEntry::Channel(channel.into()));
+        self
+    }
+    /// Appends an inclusive range of channels
+    pub fn range<C: Into<Channel>>(mut self, first: C, last: C) -> ChannelList {
+        self.0.push(ChannelEntry::Range(first.into(), last.into()));
+        self
+    }
+    /// Appends the entries of another list prefixed with a module specifier
+    pub fn module(mut self, module: &str, list: ChannelList) -> ChannelList {
+        self.0.push(ChannelEntry::Module(module.to_owned(), list.0));
+        self
+    }
+}
+
+/// Decimal numeric value encoded using an explicit formatting mode
+///
+/// Reference: IEEE 488.2: 7.7.2 - <DECIMAL NUMERIC PROGRAM DATA>
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Decimal<T> {
+    pub value: T,
+    pub format: DecimalFormat,
+}
+
+impl<T> Decimal<T> {
+    pub fn new(value: T, format: DecimalFormat) -> Decimal<T> {
+        Decimal { value, format }
+    }
+}
+
+/// Unsigned integer encoded as hexadecimal numeric data (e.g. `#H1F`)
+///
+/// Reference: IEEE 488.2: 7.7.4 - <NONDECIMAL NUMERIC PROGRAM DATA>
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Hex<T>(pub T);
+
+/// Unsigned integer encoded as octal numeric data (e.g. `#Q37`)
+///
+/// Reference: IEEE 488.2: 7.7.4 - <NONDECIMAL NUMERIC PROGRAM DATA>
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Octal<T>(pub T);
+
+/// Unsigned integer encoded as binary numeric data (e.g. `#B11111`)
+///
+/// Reference: IEEE 488.2: 7.7.4 - <NONDECIMAL NUMERIC PROGRAM DATA>
+#[repr(transparent)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Binary<T>(pub T);
+
+/// Formatting mode for decimal numeric values
+///
+/// NAN, INF and NINF are encoded the same way regardless of the mode.
+///
+/// Reference: IEEE 488.2: 8.7.2..8.7.4 - <NR1/NR2/NR3 NUMERIC RESPONSE DATA>
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum DecimalFormat {
+    /// Shortest exponential representation that round-trips to the same value (e.g. `1.5E-3`)
+    #[default]
+    Shortest,
+    /// Exponential representation with a fixed number of significant digits (e.g. `1.500E-3`)
+    ///
+    /// At least one digit is always sent, so `Significant(0)` is the same as `Significant(1)`.
+    Significant(usize),
+    /// Integer without a decimal point or exponent, rounded to the nearest integer with ties
+    /// rounded away from zero (e.g. `2`)
+    Nr1,
+    /// Explicit decimal point without an exponent (e.g. `0.0015`)
+    Nr2,
+    /// Explicit decimal point and exponent (e.g. `1.5E-3`)
+    Nr3,
+}
+
 /// Special parameter that allows the instrument to select a numeric value.
 ///
 /// Reference: SCPI 1999.0: 7.2.1.1 - DEFault
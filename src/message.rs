@@ -0,0 +1,232 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io::Write;
+
+use crate::{response::FromResponse, EncodeError, Header, HeaderForm, Parameter};
+
+/// Terminator written at the end of a program message
+///
+/// Reference: IEEE 488.2: 7.5 - <PROGRAM MESSAGE TERMINATOR>
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Terminator {
+    /// Newline character (`\n`)
+    #[default]
+    Newline,
+    /// Newline character sent together with the END message (e.g. EOI)
+    NewlineEnd,
+    /// END message sent together with the last byte, without a newline character
+    End,
+}
+
+impl Terminator {
+    /// Returns true if the transport should send the END message with the last byte
+    pub fn has_end(self) -> bool {
+        match self {
+            Terminator::Newline => false,
+            Terminator::NewlineEnd | Terminator::End => true,
+        }
+    }
+    /// Returns true if a newline character is written at the end of the message
+    pub fn has_newline(self) -> bool {
+        match self {
+            Terminator::Newline | Terminator::NewlineEnd => true,
+            Terminator::End => false,
+        }
+    }
+}
+
+/// Program message unit with a fixed header and typed parameters
+pub trait Command {
+    type Params: Parameter;
+    fn header(&self) -> Header<'static>;
+    fn params(self) -> Self::Params;
+}
+
+/// Command that makes the instrument send a response
+pub trait Query: Command {
+    type Response: for<'a> FromResponse<'a>;
+}
+
+/// Writer that assembles program message units into a complete program message
+///
+/// The END message can't be expressed through `io::Write`, so transports that
+/// support it are expected to check `Terminator::has_end` and send the END
+/// message with the last byte of the message themselves.
+///
+/// Reference: IEEE 488.2: 7.3 - Program Message Syntax
+#[derive(Debug)]
+pub struct ProgramMessage<W> {
+    w: W,
+    form: HeaderForm,
+    terminator: Terminator,
+    relative_headers: bool,
+    units: usize,
+    /// Encoded path of the previous compound header, excluding the last node
+    path: Option<Vec<u8>>,
+}
+
+impl<W: Write> ProgramMessage<W> {
+    pub fn new(w: W) -> ProgramMessage<W> {
+        ProgramMessage {
+            w,
+            form: HeaderForm::default(),
+            terminator: Terminator::default(),
+            relative_headers: false,
+            units: 0,
+            path: None,
+        }
+    }
+    /// Sets the mnemonic form used for headers
+    pub fn form(mut self, form: HeaderForm) -> ProgramMessage<W> {
+        self.form = form;
+        self
+    }
+    /// Sets the terminator written by `finish`
+    pub fn terminator(mut self, terminator: Terminator) -> ProgramMessage<W> {
+        self.terminator = terminator;
+        self
+    }
+    /// Enables relative headers for consecutive compound headers sharing the same path
+    ///
+    /// When disabled, every compound header after the first one is sent as
+    /// an absolute header (`;:`).
+    ///
+    /// Reference: SCPI 1999.0: 6.2.4 - Traversal of the Header Tree
+    pub fn relative_headers(mut self, enabled: bool) -> ProgramMessage<W> {
+        self.relative_headers = enabled;
+        self
+    }
+    /// Returns the configured terminator
+    pub fn get_terminator(&self) -> Terminator {
+        self.terminator
+    }
+    /// Returns the number of message units written so far
+    pub fn units(&self) -> usize {
+        self.units
+    }
+    /// Writes a program message unit consisting of a header and its parameters
+    ///
+    /// Use `()` for units without parameters. The parameters are encoded
+    /// into memory before anything is written, so a parameter that can't be
+    /// encoded leaves the message unchanged.
+    pub fn unit<P: Parameter>(&mut self, header: &Header, params: P) -> Result<(), EncodeError> {
+        let mut encoded = Vec::new();
+        header.encode(self.form, &mut encoded)?;
+        let mut data = Vec::new();
+        params.encode(&mut data)?;
+        if encoded.first() == Some(&b'*') {
+            // IEEE 488.2: A.1.1 - common commands don't affect the current path
+            if self.units > 0 {
+                self.w.write_all(b";")?;
+            }
+            self.w.write_all(&encoded)?;
+        } else {
+            let split = encoded.iter().rposition(|&b| b == b':');
+            let (parent, leaf) = match split {
+                Some(idx) => (&encoded[..idx], &encoded[idx + 1..]),
+                None => (&encoded[..0], &encoded[..]),
+            };
+            if self.units == 0 {
+                self.w.write_all(&encoded)?;
+            } else if self.relative_headers && self.path.as_deref() == Some(parent) {
+                self.w.write_all(b";")?;
+                self.w.write_all(leaf)?;
+            } else {
+                self.w.write_all(b";:")?;
+                self.w.write_all(&encoded)?;
+            }
+            self.path = Some(parent.to_vec());
+        }
+        if !data.is_empty() {
+            // IEEE 488.2: 7.4.3 - <PROGRAM HEADER SEPARATOR>
+            self.w.write_all(b" ")?;
+            self.w.write_all(&data)?;
+        }
+        self.units += 1;
+        Ok(())
+    }
+    /// Writes a program message unit for the given command
+    pub fn command<C: Command>(&mut self, command: C) -> Result<(), EncodeError> {
+        let header = command.header();
+        self.unit(&header, command.params())
+    }
+    /// Writes the terminator and returns the underlying writer
+    pub fn finish(mut self) -> Result<W, EncodeError> {
+        if self.terminator.has_newline() {
+            self.w.write_all(b"\n")?;
+        }
+        Ok(self.w)
+    }
+}
+
+#[test]
+fn test_program_message_single_unit() {
+    let mut msg = ProgramMessage::new(Vec::new());
+    msg.unit(&Header::common("RST"), ()).unwrap();
+    assert_eq!(msg.finish().unwrap(), b"*RST\n");
+}
+
+#[test]
+fn test_program_message_units() {
+    let mut msg = ProgramMessage::new(Vec::new());
+    let volt = Header::new().node("SOURce").node("VOLTage");
+    let curr = Header::new().node("SOURce").node("CURRent");
+    msg.unit(&volt, 1.5).unwrap();
+    msg.unit(&curr, (0.1, crate::Discrete("ON"))).unwrap();
+    msg.unit(&Header::common("OPC").query(), ()).unwrap();
+    msg.unit(&Header::new().node("OUTPut"), true).unwrap();
+    assert_eq!(msg.units(), 4);
+    assert_eq!(
+        msg.finish().unwrap(),
+        b"SOUR:VOLT 1.5E0;:SOUR:CURR 1E-1,ON;*OPC?;:OUTP 1\n"
+    );
+}
+
+#[test]
+fn test_program_message_relative_headers() {
+    let mut msg = ProgramMessage::new(Vec::new())
+        .form(HeaderForm::Long)
+        .relative_headers(true);
+    msg.unit(&Header::new().node("SOURce").node("VOLTage"), 1u8)
+        .unwrap();
+    msg.unit(&Header::common("WAI"), ()).unwrap();
+    msg.unit(&Header::new().node("SOURce").node("CURRent"), 2u8)
+        .unwrap();
+    msg.unit(&Header::new().node("OUTPut"), true).unwrap();
+    msg.unit(&Header::new().node("TRIGger"), ()).unwrap();
+    assert_eq!(
+        msg.finish().unwrap(),
+        b"SOURCE:VOLTAGE 1;*WAI;CURRENT 2;:OUTPUT 1;TRIGGER\n"
+    );
+}
+
+#[test]
+fn test_program_message_invalid_parameter() {
+    let mut msg = ProgramMessage::new(Vec::new());
+    msg.unit(&Header::common("RST"), ()).unwrap();
+    let header = Header::new().node("DISPlay").node("TEXT");
+    assert!(msg.unit(&header, ("ok", "caf\u{e9}")).is_err());
+    assert!(msg.unit(&header, 1E99).is_err());
+    assert_eq!(msg.units(), 1);
+    msg.unit(&header, "ok").unwrap();
+    assert_eq!(msg.finish().unwrap(), b"*RST;:DISP:TEXT \"ok\"\n");
+}
+
+#[test]
+fn test_program_message_terminator() {
+    let mut msg = ProgramMessage::new(Vec::new()).terminator(Terminator::End);
+    msg.unit(&Header::common("TRG"), ()).unwrap();
+    assert!(msg.get_terminator().has_end());
+    assert_eq!(msg.finish().unwrap(), b"*TRG");
+}
+
+#[test]
+fn test_program_message_indefinite_block() {
+    let mut msg = ProgramMessage::new(Vec::new()).terminator(Terminator::NewlineEnd);
+    let header = Header::new().node("DATA");
+    msg.unit(&header, crate::IndefiniteBlock(&b"\x01\x02"[..]))
+        .unwrap();
+    assert_eq!(msg.finish().unwrap(), b"DATA #0\x01\x02\n");
+}
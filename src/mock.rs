@@ -0,0 +1,394 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    io::{self, BufReader, Write},
+    net::{SocketAddr, TcpListener},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    dispatch::{CommandTree, Pattern},
+    error_queue::ScpiError,
+    program,
+    status::{StandardEventStatus, StatusModel},
+    transport::read_program_message,
+    Response,
+};
+
+/// Error returned when a mock instrument session didn't go as scripted
+#[derive(Debug)]
+pub enum MockError {
+    /// First received message that matched neither the next expectation nor a built-in command
+    Unexpected(Vec<u8>),
+    /// Number of expectations left when the client disconnected
+    Unmet(usize),
+    Io(io::Error),
+}
+
+impl fmt::Display for MockError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MockError::Unexpected(message) => write!(
+                f,
+                "unexpected message {:?}",
+                String::from_utf8_lossy(message)
+            ),
+            MockError::Unmet(count) => write!(f, "{} expected messages were not received", count),
+            MockError::Io(err) => write!(f, "I/O error: {}", err),
+        }
+    }
+}
+
+impl Error for MockError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MockError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for MockError {
+    fn from(err: io::Error) -> Self {
+        MockError::Io(err)
+    }
+}
+
+#[derive(Debug)]
+enum Matcher {
+    Exact(Vec<u8>),
+    Header(Pattern),
+}
+
+impl Matcher {
+    fn matches(&self, message: &[u8]) -> bool {
+        match self {
+            Matcher::Exact(expected) => expected == message,
+            Matcher::Header(pattern) => {
+                let mut units = program::units(message);
+                match (units.next(), units.next()) {
+                    (Some(Ok(unit)), None) => pattern.matches(&unit.header).is_some(),
+                    _ => false,
+                }
+            }
+        }
+    }
+}
+
+/// Program message expected by a mock instrument, and the reaction to it
+#[derive(Debug)]
+pub struct Expectation {
+    matcher: Matcher,
+    response: Vec<u8>,
+    errors: Vec<ScpiError>,
+    delay: Option<Duration>,
+}
+
+impl Expectation {
+    fn new(matcher: Matcher) -> Expectation {
+        Expectation {
+            matcher,
+            response: Vec::new(),
+            errors: Vec::new(),
+            delay: None,
+        }
+    }
+    /// Expects a program message that is exactly `message`, excluding the terminator
+    pub fn exact<M: AsRef<[u8]>>(message: M) -> Expectation {
+        Expectation::new(Matcher::Exact(message.as_ref().to_vec()))
+    }
+    /// Expects a program message with a single message unit whose header matches the pattern
+    ///
+    /// The pattern uses the same notation as `CommandTree::command` (e.g.
+    /// `[SOURce#]:VOLTage[:LEVel]?`), and any program data is accepted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is not a valid header pattern.
+    pub fn header(pattern: &str) -> Expectation {
+        Expectation::new(Matcher::Header(Pattern::parse(pattern)))
+    }
+    /// Responds with a newline terminated response message containing the value
+    ///
+    /// # Panics
+    ///
+    /// Panics if the value can't be encoded as response data.
+    pub fn respond<R: Response>(mut self, value: R) -> Expectation {
+        self.response.clear();
+        value
+            .encode_response(&mut self.response)
+            .expect("invalid response data");
+        self.response.push(b'\n');
+        self
+    }
+    /// Responds with raw bytes, which are sent as-is without adding a terminator
+    pub fn respond_raw<B: AsRef<[u8]>>(mut self, response: B) -> Expectation {
+        self.response = response.as_ref().to_vec();
+        self
+    }
+    /// Pushes an error to the error/event queue when the message is received
+    pub fn error(mut self, error: ScpiError) -> Expectation {
+        self.errors.push(error);
+        self
+    }
+    /// Waits before responding, simulating a slow operation
+    pub fn delay(mut self, delay: Duration) -> Expectation {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// Scriptable instrument that serves a single client on a loopback raw SCPI socket
+///
+/// Received program messages are matched against the expectations in order.
+/// Messages that don't match the next expectation are executed by a built-in
+/// command tree that implements `SYSTem:ERRor[:NEXT]?`, `SYSTem:ERRor:COUNt?`
+/// and the commands of `StatusModel`, so drivers can check for errors and
+/// poll status without scripting those queries. Anything else is reported
+/// as an unexpected message when the server is joined.
+#[derive(Debug)]
+pub struct MockInstrument {
+    expectations: VecDeque<Expectation>,
+    tree: CommandTree<StatusModel>,
+    status: StatusModel,
+    accept_timeout: Duration,
+}
+
+impl Default for MockInstrument {
+    fn default() -> Self {
+        MockInstrument::new()
+    }
+}
+
+impl MockInstrument {
+    /// Time to wait for the client to connect, used by default
+    pub const DEFAULT_ACCEPT_TIMEOUT: Duration = Duration::from_secs(10);
+
+    pub fn new() -> MockInstrument {
+        MockInstrument {
+            expectations: VecDeque::new(),
+            tree: StatusModel::commands(CommandTree::new(), |status| status),
+            status: StatusModel::new(),
+            accept_timeout: MockInstrument::DEFAULT_ACCEPT_TIMEOUT,
+        }
+    }
+    /// Sets the time to wait for the client to connect
+    ///
+    /// If no client connects in time, the server fails with `TimedOut`, so a
+    /// test that fails before connecting doesn't block in `MockServer::join`.
+    pub fn accept_timeout(mut self, timeout: Duration) -> MockInstrument {
+        self.accept_timeout = timeout;
+        self
+    }
+    /// Appends an expected program message to the script
+    pub fn expect(mut self, expectation: Expectation) -> MockInstrument {
+        self.expectations.push_back(expectation);
+        self
+    }
+    /// Pushes an error to the error/event queue before the session starts
+    pub fn error(mut self, error: ScpiError) -> MockInstrument {
+        self.tree.errors().push(error);
+        self
+    }
+    /// Starts serving on an ephemeral loopback port in a background thread
+    pub fn spawn(self) -> io::Result<MockServer> {
+        let listener = TcpListener::bind(("127.0.0.1", 0))?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let handle = thread::spawn(move || self.serve(listener));
+        Ok(MockServer { addr, handle })
+    }
+    fn serve(mut self, listener: TcpListener) -> Result<(), MockError> {
+        let deadline = Instant::now() + self.accept_timeout;
+        let stream = loop {
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    if Instant::now() >= deadline {
+                        return Err(io::Error::from(io::ErrorKind::TimedOut).into());
+                    }
+                    thread::sleep(Duration::from_millis(5));
+                }
+                Err(err) => return Err(err.into()),
+            }
+        };
+        // Accepted streams inherit non-blocking mode on some platforms
+        stream.set_nonblocking(false)?;
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+        let mut unexpected = None;
+        loop {
+            let message = match read_program_message(&mut reader) {
+                Ok(message) => message,
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err.into()),
+            };
+            let response = self.handle(&message, &mut unexpected);
+            if !response.is_empty() {
+                writer.write_all(&response)?;
+                writer.flush()?;
+            }
+        }
+        match unexpected {
+            Some(message) => Err(MockError::Unexpected(message)),
+            None if !self.expectations.is_empty() => Err(MockError::Unmet(self.expectations.len())),
+            None => Ok(()),
+        }
+    }
+    fn handle(&mut self, message: &[u8], unexpected: &mut Option<Vec<u8>>) -> Vec<u8> {
+        let content = message.strip_suffix(b"\n").unwrap_or(message);
+        let content = content.strip_suffix(b"\r").unwrap_or(content);
+        let matched = self
+            .expectations
+            .front()
+            .is_some_and(|expectation| expectation.matcher.matches(content));
+        if matched {
+            let expectation = self.expectations.pop_front().unwrap();
+            for error in expectation.errors {
+                self.tree.errors().push(error);
+            }
+            if let Some(delay) = expectation.delay {
+                thread::sleep(delay);
+            }
+            return expectation.response;
+        }
+        // Command errors of the built-in tree mean that the message wasn't understood
+        let events = self.tree.errors().take_events();
+        self.status.set_event(events);
+        let response = self.tree.execute(&mut self.status, message);
+        let events = self.tree.errors().take_events();
+        self.status.set_event(events);
+        if events.contains(StandardEventStatus::CME) && unexpected.is_none() {
+            *unexpected = Some(content.to_vec());
+        }
+        response
+    }
+}
+
+/// Running mock instrument
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    handle: JoinHandle<Result<(), MockError>>,
+}
+
+impl MockServer {
+    /// Returns the loopback address the instrument listens on
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    /// Waits for the client to disconnect and checks that the session went as scripted
+    pub fn join(self) -> Result<(), MockError> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+#[cfg(test)]
+fn connect(server: &MockServer) -> crate::transport::TcpTransport {
+    crate::transport::TcpTransport::connect(server.addr(), Duration::from_secs(5)).unwrap()
+}
+
+#[test]
+fn test_mock_instrument_session() {
+    use crate::{
+        common::{IdnQuery, Rst},
+        response,
+        transport::Transport,
+        Block, Discrete, Header,
+    };
+    let server = MockInstrument::new()
+        .expect(Expectation::exact("*RST"))
+        .expect(Expectation::exact("*IDN?").respond(("ACME", Discrete("MOCK"), 42, "1.0")))
+        .expect(Expectation::header("[SOURce#]:VOLTage[:LEVel]"))
+        .expect(Expectation::header("[SOURce#]:VOLTage[:LEVel]?").respond(5.0))
+        .expect(
+            Expectation::exact(":TRAC:DATA?")
+                .respond(Block(b"\x00\n\x01"))
+                .delay(Duration::from_millis(10)),
+        )
+        .expect(
+            Expectation::header("OUTPut[:STATe]").error(ScpiError::new(-221, "Settings conflict")),
+        )
+        .spawn()
+        .unwrap();
+    let mut transport = connect(&server);
+    transport.send(Rst).unwrap();
+    assert_eq!(transport.query(IdnQuery).unwrap().model, "MOCK");
+
+    let mut msg = transport.message();
+    msg.unit(&Header::new().node("SOUR").suffix(2).node("VOLT"), 5.0)
+        .unwrap();
+    transport.write_message(&msg.finish().unwrap()).unwrap();
+    transport.write_message(b"VOLTAGE:LEVEL?\n").unwrap();
+    assert_eq!(
+        response::parse::<f64>(&transport.read_message().unwrap()),
+        Ok(5.0)
+    );
+    transport.write_message(b":TRAC:DATA?\n").unwrap();
+    assert_eq!(transport.read_message().unwrap(), b"#13\x00\n\x01\n");
+
+    transport.write_message(b"OUTP ON\n").unwrap();
+    transport.write_message(b"*STB?;:SYST:ERR?;ERR?\n").unwrap();
+    assert_eq!(
+        transport.read_message().unwrap(),
+        b"4;-221,\"Settings conflict\";0,\"No error\"\n"
+    );
+    drop(transport);
+    server.join().unwrap();
+}
+
+#[test]
+fn test_mock_instrument_unexpected() {
+    use crate::transport::Transport;
+    let server = MockInstrument::new()
+        .expect(Expectation::exact("*RST"))
+        .error(ScpiError::new(-350, "Queue overflow"))
+        .spawn()
+        .unwrap();
+    let mut transport = connect(&server);
+    transport.write_message(b"SYST:ERR?\n").unwrap();
+    assert_eq!(
+        transport.read_message().unwrap(),
+        b"-350,\"Queue overflow\"\n"
+    );
+    transport.write_message(b"*CLS;*RST\n").unwrap();
+    transport.write_message(b"SYST:ERR:COUN?\n").unwrap();
+    assert_eq!(transport.read_message().unwrap(), b"1\n");
+    drop(transport);
+    match server.join() {
+        Err(MockError::Unexpected(message)) => assert_eq!(message, b"*CLS;*RST"),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn test_mock_instrument_unmet() {
+    let server = MockInstrument::new()
+        .expect(Expectation::exact("*RST"))
+        .spawn()
+        .unwrap();
+    drop(connect(&server));
+    assert!(matches!(server.join(), Err(MockError::Unmet(1))));
+}
+
+#[test]
+fn test_mock_instrument_accept_timeout() {
+    let server = MockInstrument::new()
+        .accept_timeout(Duration::from_millis(20))
+        .spawn()
+        .unwrap();
+    match server.join() {
+        Err(MockError::Io(err)) => assert_eq!(err.kind(), io::ErrorKind::TimedOut),
+        result => panic!("unexpected result {:?}", result),
+    }
+}
@@ -2,23 +2,46 @@
 //
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
-use std::io::{self, Write};
+use std::{
+    fmt,
+    io::{self, Read, Write},
+};
 
-use crate::{Block, DefaultValue, Discrete, Limit, ScpiDisplay, Step};
+use crate::{
+    Binary, Block, Channel, ChannelEntry, ChannelList, Decimal, DecimalFormat, DefaultValue,
+    Discrete, EncodeError, Hex, IndefiniteBlock, Limit, Octal, ScpiDisplay, Step, StreamedBlock,
+};
 
 /// Trait for types that can be used as SCPI command/query parameters
 pub trait Parameter {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()>;
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError>;
 }
 
-impl Parameter for Discrete {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
-        // TODO: return error instead
-        debug_assert!(self
-            .0
-            .chars()
-            .all(|ch| { ch.is_ascii() && !(ch.is_ascii_control() && !ch.is_ascii_whitespace()) }));
-        w.write_all(self.0.as_bytes())
+/// Returns true if the character can be sent as-is in program data
+fn is_allowed_char(ch: char) -> bool {
+    ch.is_ascii() && (!ch.is_ascii_control() || ch.is_ascii_whitespace())
+}
+
+/// Checks that all characters of the string can be sent as-is in program data
+fn check_chars(s: &str) -> Result<(), EncodeError> {
+    match s.char_indices().find(|&(_, ch)| !is_allowed_char(ch)) {
+        Some((index, ch)) => Err(EncodeError::InvalidCharacter { ch, index }),
+        None => Ok(()),
+    }
+}
+
+impl<'a> Parameter for Discrete<'a> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        check_chars(self.0)?;
+        // IEEE 488.2: 7.7.1 - <CHARACTER PROGRAM DATA>
+        let mut bytes = self.0.bytes();
+        let is_mnemonic = bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+            && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if !is_mnemonic {
+            return Err(EncodeError::InvalidMnemonic(self.0.to_owned()));
+        }
+        w.write_all(self.0.as_bytes())?;
+        Ok(())
     }
 }
 
@@ -29,22 +52,44 @@ fn test_discrete_parameter() {
     assert_eq!(buf, b"TEST");
 }
 
+#[test]
+fn test_discrete_parameter_invalid_character() {
+    let mut buf = Vec::new();
+    let result = Discrete("TE\u{e4}ST").encode(&mut buf);
+    assert!(matches!(
+        result,
+        Err(EncodeError::InvalidCharacter {
+            ch: '\u{e4}',
+            index: 2
+        })
+    ));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_discrete_parameter_invalid_mnemonic() {
+    let mut buf = Vec::new();
+    for &invalid in &["", "1ST", "TWO WORDS", "A,B"] {
+        let result = Discrete(invalid).encode(&mut buf);
+        assert!(matches!(result, Err(EncodeError::InvalidMnemonic(ref m)) if m == invalid));
+    }
+    assert!(buf.is_empty());
+}
+
 impl Parameter for &str {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // Only ASCII is allowed
+        check_chars(self)?;
         w.write_all(b"\"")?;
         for ch in self.chars() {
             match ch {
                 // Double quotes are escaped by duplicating them
                 '"' => w.write_all(b"\"\"")?,
-                // Only ASCII is allowed
-                ch if ch.is_ascii() && !(ch.is_ascii_control() && !ch.is_ascii_whitespace()) => {
-                    w.write_all(&[ch as u8])?
-                }
-                // TODO: return error instead
-                _ => w.write_all(b"*")?,
+                ch => w.write_all(&[ch as u8])?,
             }
         }
-        w.write_all(b"\"")
+        w.write_all(b"\"")?;
+        Ok(())
     }
 }
 
@@ -62,19 +107,52 @@ fn test_str_parameter_escape() {
     assert_eq!(buf, br#""what if ""quotes"" break 'stuff'?""#);
 }
 
+#[test]
+fn test_str_parameter_invalid_character() {
+    let mut buf = Vec::new();
+    let result = "caf\u{e9}".encode(&mut buf);
+    assert!(matches!(
+        result,
+        Err(EncodeError::InvalidCharacter {
+            ch: '\u{e9}',
+            index: 3
+        })
+    ));
+    let result = "bell\x07".encode(&mut buf);
+    assert!(matches!(
+        result,
+        Err(EncodeError::InvalidCharacter {
+            ch: '\x07',
+            index: 4
+        })
+    ));
+    assert!(buf.is_empty());
+}
+
+/// Encodes the header of a definite length arbitrary block
+fn encode_block_header<W: Write>(len: u64, w: &mut W) -> Result<(), EncodeError> {
+    let mut buf = [0; 64];
+    let remaining = {
+        let mut buf_slice = &mut buf[..];
+        write!(buf_slice, "{}", len)?;
+        buf_slice.len()
+    };
+    let digits = buf.len() - remaining;
+    // The number of length digits must fit in a single digit
+    if digits > 9 {
+        return Err(EncodeError::OutOfRange(len as f64));
+    }
+    w.write_all(b"#")?;
+    w.write_all(&[b'0' + (digits as u8)])?;
+    w.write_all(&buf[..digits])?;
+    Ok(())
+}
+
 impl<'a> Parameter for Block<'a> {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
-        w.write_all(b"#")?;
-        let mut buf = [0; 64];
-        let remaining = {
-            let mut buf_slice = &mut buf[..];
-            write!(buf_slice, "{}", self.0.len())?;
-            buf_slice.len()
-        };
-        let digits = buf.len() - remaining;
-        w.write_all(&[b'0' + (digits as u8)])?;
-        w.write_all(&buf[..digits])?;
-        w.write_all(self.0)
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_block_header(self.0.len() as u64, w)?;
+        w.write_all(self.0)?;
+        Ok(())
     }
 }
 
@@ -85,83 +163,423 @@ fn test_block_parameter() {
     assert_eq!(buf, b"#13\x11\x22\x33");
 }
 
-impl Parameter for f32 {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
-        // SCPI 1999.0: 7.2 - Decimal Numeric Program Data
-        // TODO: return error instead
-        debug_assert!(!(self > 9.9E37 || self < -9.9E37));
-        if self.is_finite() {
-            write!(w, "{:E}", self)
-        } else if self.is_nan() {
-            // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
-            w.write_all(b"NAN")
+fn encode_channel<W: Write>(channel: &Channel, w: &mut W) -> Result<(), EncodeError> {
+    if channel.0.is_empty() {
+        return Err(EncodeError::EmptyChannel);
+    }
+    for (idx, dimension) in channel.0.iter().enumerate() {
+        if idx > 0 {
+            w.write_all(b"!")?;
+        }
+        write!(w, "{}", dimension)?;
+    }
+    Ok(())
+}
+
+fn encode_channel_entries<W: Write>(
+    entries: &[ChannelEntry],
+    w: &mut W,
+) -> Result<(), EncodeError> {
+    for (idx, entry) in entries.iter().enumerate() {
+        if idx > 0 {
+            w.write_all(b",")?;
+        }
+        match entry {
+            ChannelEntry::Channel(channel) => encode_channel(channel, w)?,
+            ChannelEntry::Range(first, last) => {
+                encode_channel(first, w)?;
+                w.write_all(b":")?;
+                encode_channel(last, w)?;
+            }
+            ChannelEntry::Module(module, entries) => {
+                Discrete(module).encode(w)?;
+                w.write_all(b"(")?;
+                encode_channel_entries(entries, w)?;
+                w.write_all(b")")?;
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Parameter for &ChannelList {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // Validate the whole list before writing anything
+        let mut buf = Vec::new();
+        encode_channel_entries(&self.0, &mut buf)?;
+        w.write_all(b"(@")?;
+        w.write_all(&buf)?;
+        w.write_all(b")")?;
+        Ok(())
+    }
+}
+
+impl Parameter for ChannelList {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        (&self).encode(w)
+    }
+}
+
+#[test]
+fn test_channel_list_parameter() {
+    let mut buf = Vec::new();
+    ChannelList::new()
+        .channel(1)
+        .range(3, 5)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"(@1,3:5)");
+}
+
+#[test]
+fn test_channel_list_parameter_dimensions() {
+    let mut buf = Vec::new();
+    ChannelList::new()
+        .channel(Channel(vec![1, 2]))
+        .range(Channel(vec![3, 4]), Channel(vec![3, 8]))
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"(@1!2,3!4:3!8)");
+}
+
+#[test]
+fn test_channel_list_parameter_module() {
+    let mut buf = Vec::new();
+    ChannelList::new()
+        .module("CARD1", ChannelList::new().channel(1).range(3, 5))
+        .module("CARD2", ChannelList::new().channel(7))
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"(@CARD1(1,3:5),CARD2(7))");
+}
+
+#[test]
+fn test_channel_list_parameter_invalid() {
+    let mut buf = Vec::new();
+    let result = ChannelList::new()
+        .channel(1)
+        .channel(Channel(vec![]))
+        .encode(&mut buf);
+    assert!(matches!(result, Err(EncodeError::EmptyChannel)));
+    let result = ChannelList::new()
+        .module("CARD 1", ChannelList::new().channel(1))
+        .encode(&mut buf);
+    assert!(matches!(result, Err(EncodeError::InvalidMnemonic(ref m)) if m == "CARD 1"));
+    assert!(buf.is_empty());
+}
+
+impl<R: Read> Parameter for StreamedBlock<R> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_block_header(self.len, w)?;
+        let actual = io::copy(&mut self.source.take(self.len), w)?;
+        if actual != self.len {
+            return Err(EncodeError::BlockLength {
+                expected: self.len,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn test_streamed_block_parameter() {
+    let mut buf = Vec::new();
+    let data = [0x11u8, 0x22, 0x33, 0x44];
+    StreamedBlock::new(3, &data[..]).encode(&mut buf).unwrap();
+    assert_eq!(buf, b"#13\x11\x22\x33");
+}
+
+#[test]
+fn test_streamed_block_parameter_short_source() {
+    let mut buf = Vec::new();
+    let result = StreamedBlock::new(12, &b"short"[..]).encode(&mut buf);
+    assert!(matches!(
+        result,
+        Err(EncodeError::BlockLength {
+            expected: 12,
+            actual: 5
+        })
+    ));
+}
+
+impl<R: Read> Parameter for IndefiniteBlock<R> {
+    fn encode<W: Write>(mut self, w: &mut W) -> Result<(), EncodeError> {
+        w.write_all(b"#0")?;
+        io::copy(&mut self.0, w)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_indefinite_block_parameter() {
+    let mut buf = Vec::new();
+    IndefiniteBlock(&b"\x00\n\x01"[..])
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#0\x00\n\x01");
+}
+
+/// Encodes a floating point value as decimal numeric program data
+fn encode_decimal<T, W>(value: T, format: DecimalFormat, w: &mut W) -> Result<(), EncodeError>
+where
+    T: Copy + Into<f64> + fmt::Display + fmt::UpperExp,
+    W: Write,
+{
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
+        w.write_all(b"NAN")?;
+    } else if wide.is_infinite() {
+        // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+        if wide.is_sign_positive() {
+            w.write_all(b"INF")?;
         } else {
-            // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
-            if self.is_sign_positive() {
-                w.write_all(b"INF")
-            } else {
-                w.write_all(b"NINF")
+            w.write_all(b"NINF")?;
+        }
+    } else {
+        // SCPI 1999.0: 7.2 - Decimal Numeric Program Data
+        if !(-9.9E37..=9.9E37).contains(&wide) {
+            return Err(EncodeError::OutOfRange(wide));
+        }
+        match format {
+            DecimalFormat::Shortest => write!(w, "{:E}", value)?,
+            DecimalFormat::Significant(digits) => {
+                write!(w, "{:.*E}", digits.saturating_sub(1), value)?
+            }
+            // Ties are rounded away from zero, and negative zero is sent as `0`
+            DecimalFormat::Nr1 => write!(w, "{:.0}", wide.round() + 0.0)?,
+            DecimalFormat::Nr2 => {
+                let text = value.to_string();
+                w.write_all(text.as_bytes())?;
+                if !text.contains('.') {
+                    w.write_all(b".0")?;
+                }
+            }
+            DecimalFormat::Nr3 => {
+                let text = format!("{:E}", value);
+                let (mantissa, exponent) = text.split_at(text.find('E').unwrap_or(text.len()));
+                w.write_all(mantissa.as_bytes())?;
+                if !mantissa.contains('.') {
+                    w.write_all(b".0")?;
+                }
+                w.write_all(exponent.as_bytes())?;
             }
         }
     }
+    Ok(())
+}
+
+impl Parameter for f32 {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_decimal(self, DecimalFormat::default(), w)
+    }
+}
+
+impl Parameter for f64 {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_decimal(self, DecimalFormat::default(), w)
+    }
+}
+
+impl Parameter for Decimal<f32> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_decimal(self.value, self.format, w)
+    }
+}
+
+impl Parameter for Decimal<f64> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_decimal(self.value, self.format, w)
+    }
 }
 
 #[test]
 fn test_f32_parameter_positive() {
     let mut buf = Vec::new();
-    1.234567E11.encode(&mut buf).unwrap();
+    1.234567E11f32.encode(&mut buf).unwrap();
     assert_eq!(buf, b"1.234567E11");
 }
 
 #[test]
 fn test_f32_parameter_negative() {
     let mut buf = Vec::new();
-    (-1.234567E-11).encode(&mut buf).unwrap();
+    (-1.234567E-11f32).encode(&mut buf).unwrap();
     assert_eq!(buf, b"-1.234567E-11");
 }
 
+#[test]
+fn test_f32_parameter_special() {
+    let mut buf = Vec::new();
+    (f32::NAN, f32::INFINITY, f32::NEG_INFINITY)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"NAN,INF,NINF");
+}
+
+#[test]
+fn test_f32_parameter_out_of_range() {
+    let mut buf = Vec::new();
+    assert!(matches!(
+        1E38f32.encode(&mut buf),
+        Err(EncodeError::OutOfRange(value)) if value == f64::from(1E38f32)
+    ));
+    assert!(matches!(
+        (-1E38f32).encode(&mut buf),
+        Err(EncodeError::OutOfRange(_))
+    ));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_f64_parameter() {
+    let mut buf = Vec::new();
+    1.000000001E9.encode(&mut buf).unwrap();
+    assert_eq!(buf, b"1.000000001E9");
+}
+
+#[test]
+fn test_f64_parameter_special() {
+    let mut buf = Vec::new();
+    (f64::NAN, f64::INFINITY, f64::NEG_INFINITY)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"NAN,INF,NINF");
+}
+
+#[test]
+fn test_f64_parameter_out_of_range() {
+    let mut buf = Vec::new();
+    assert!(matches!(
+        (-1E300).encode(&mut buf),
+        Err(EncodeError::OutOfRange(value)) if value == -1E300
+    ));
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn test_decimal_parameter_formats() {
+    fn encode(value: f64, format: DecimalFormat) -> String {
+        let mut buf = Vec::new();
+        Decimal::new(value, format).encode(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+    assert_eq!(encode(1234.5, DecimalFormat::Shortest), "1.2345E3");
+    assert_eq!(encode(1234.5, DecimalFormat::Significant(3)), "1.23E3");
+    assert_eq!(encode(1234.5, DecimalFormat::Significant(6)), "1.23450E3");
+    assert_eq!(encode(1234.5, DecimalFormat::Nr1), "1235");
+    assert_eq!(encode(1233.5, DecimalFormat::Nr1), "1234");
+    assert_eq!(encode(-1234.5, DecimalFormat::Nr1), "-1235");
+    assert_eq!(encode(-0.4, DecimalFormat::Nr1), "0");
+    assert_eq!(encode(1234.5, DecimalFormat::Significant(0)), "1E3");
+    assert_eq!(encode(1234.5, DecimalFormat::Nr2), "1234.5");
+    assert_eq!(encode(1E9, DecimalFormat::Nr2), "1000000000.0");
+    assert_eq!(encode(1234.5, DecimalFormat::Nr3), "1.2345E3");
+    assert_eq!(encode(1E-3, DecimalFormat::Nr3), "1.0E-3");
+    assert_eq!(encode(f64::NAN, DecimalFormat::Nr1), "NAN");
+}
+
+#[test]
+fn test_decimal_parameter_f32() {
+    let mut buf = Vec::new();
+    Decimal::new(0.1f32, DecimalFormat::Nr2)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"0.1");
+}
+
+macro_rules! impl_non_decimal_parameter {
+    ($($ty:ident),*) => {
+        $(
+            impl Parameter for Hex<$ty> {
+                fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#H{:X}", self.0)?;
+                    Ok(())
+                }
+            }
+
+            impl Parameter for Octal<$ty> {
+                fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#Q{:o}", self.0)?;
+                    Ok(())
+                }
+            }
+
+            impl Parameter for Binary<$ty> {
+                fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#B{:b}", self.0)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_non_decimal_parameter!(u8, u16, u32, u64, usize);
+
+#[test]
+fn test_non_decimal_parameter() {
+    let mut buf = Vec::new();
+    (Hex(0x1fu8), Octal(0o37u16), Binary(0b11111u32))
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#H1F,#Q37,#B11111");
+}
+
+#[test]
+fn test_non_decimal_parameter_zero() {
+    let mut buf = Vec::new();
+    (Hex(0u64), Binary(0usize)).encode(&mut buf).unwrap();
+    assert_eq!(buf, b"#H0,#B0");
+}
+
 impl Parameter for DefaultValue {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
-        w.write_all(b"DEF")
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        w.write_all(b"DEF")?;
+        Ok(())
     }
 }
 
 impl Parameter for Limit {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         w.write_all(match self {
             Limit::Min => b"MIN",
             Limit::Max => b"MAX",
-        })
+        })?;
+        Ok(())
     }
 }
 
 impl Parameter for Step {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         w.write_all(match self {
             Step::Up => b"UP",
             Step::Down => b"DOWN",
-        })
+        })?;
+        Ok(())
     }
 }
 
 impl Parameter for bool {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         // SCPI 1999.0: 7.3 - Boolean Program Data
         w.write_all(match self {
             true => b"1",
             false => b"0",
-        })
+        })?;
+        Ok(())
     }
 }
 
 impl<T: ScpiDisplay> Parameter for T {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
-        write!(w, "{}", self)
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        write!(w, "{}", self)?;
+        Ok(())
     }
 }
 
 impl Parameter for () {
-    fn encode<W>(self, _w: &mut W) -> io::Result<()> {
+    fn encode<W>(self, _w: &mut W) -> Result<(), EncodeError> {
         Ok(())
     }
 }
@@ -171,7 +589,7 @@ where
     A: Parameter,
     B: Parameter,
 {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         self.0.encode(w)?;
         w.write_all(b",")?;
         self.1.encode(w)
@@ -191,7 +609,7 @@ where
     B: Parameter,
     C: Parameter,
 {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         self.0.encode(w)?;
         w.write_all(b",")?;
         self.1.encode(w)?;
@@ -214,7 +632,7 @@ where
     C: Parameter,
     D: Parameter,
 {
-    fn encode<W: Write>(self, w: &mut W) -> io::Result<()> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
         self.0.encode(w)?;
         w.write_all(b",")?;
         self.1.encode(w)?;
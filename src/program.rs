@@ -0,0 +1,1253 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{convert::TryFrom, str};
+
+use crate::{
+    response, Binary, Block, ChannelList, DecodeError, DefaultValue, Discrete, Hex, Limit, Octal,
+    ParseError, Step,
+};
+
+/// Maximum length of a program mnemonic, excluding its numeric suffix
+///
+/// Reference: IEEE 488.2: 7.6.1.2 - Encoding syntax
+const MAX_MNEMONIC_LEN: usize = 12;
+
+/// Program mnemonic with an optional numeric suffix (e.g. `MEAS2`)
+///
+/// Reference: SCPI 1999.0: 6.2.5.2 - Numeric Suffixes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Mnemonic<'a> {
+    pub name: &'a str,
+    pub suffix: Option<u32>,
+}
+
+impl<'a> Mnemonic<'a> {
+    /// Returns true if the mnemonic is the short or long form of a node written like `VOLTage`
+    ///
+    /// The short form consists of the leading uppercase characters and digits.
+    ///
+    /// Reference: SCPI 1999.0: 6.2.1 - Creating Mnemonics
+    pub fn matches(&self, node: &str) -> bool {
+        let short_len = node.bytes().take_while(|b| !b.is_ascii_lowercase()).count();
+        self.name.eq_ignore_ascii_case(&node[..short_len]) || self.name.eq_ignore_ascii_case(node)
+    }
+}
+
+/// Header of a received program message unit, resolved to an absolute path
+///
+/// Reference: IEEE 488.2: 7.6 - <PROGRAM HEADER>
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ProgramHeader<'a> {
+    /// Common command header (e.g. `*RST`)
+    pub common: bool,
+    pub nodes: Vec<Mnemonic<'a>>,
+    /// Query header ending with `?`
+    pub query: bool,
+}
+
+/// Single program message unit with its header and unparsed program data
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Unit<'a> {
+    pub header: ProgramHeader<'a>,
+    /// Byte offset of the header in the program message
+    pub position: usize,
+    input: &'a [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'a> Unit<'a> {
+    /// Returns true if the unit has any program data
+    pub fn has_params(&self) -> bool {
+        self.start < self.end
+    }
+    /// Returns a parser for the program data of the unit
+    pub fn params(&self) -> Parser<'a> {
+        Parser {
+            input: self.input,
+            pos: self.start,
+            end: self.end,
+        }
+    }
+    /// Parses all program data of the unit
+    pub fn parse<T: FromProgram<'a>>(&self) -> Result<T, ParseError> {
+        let mut parser = self.params();
+        let value = parser.parse()?;
+        parser.finish()?;
+        Ok(value)
+    }
+}
+
+/// Returns an iterator over the message units of one or more program messages
+///
+/// Relative headers after `;` are resolved using the path of the previous
+/// compound header, and the path is reset after every message terminator.
+/// The iterator stops after the first error, because the rest of the
+/// message can't be interpreted reliably.
+///
+/// Reference: IEEE 488.2: 7.3 - <PROGRAM MESSAGE>, SCPI 1999.0: 6.2.4 - Traversal of the Header Tree
+pub fn units(input: &[u8]) -> Units<'_> {
+    Units {
+        input,
+        pos: 0,
+        path: Vec::new(),
+        failed: false,
+    }
+}
+
+/// Iterator returned by `units`
+#[derive(Clone, Debug)]
+pub struct Units<'a> {
+    input: &'a [u8],
+    pos: usize,
+    path: Vec<Mnemonic<'a>>,
+    failed: bool,
+}
+
+impl<'a> Iterator for Units<'a> {
+    type Item = Result<Unit<'a>, ParseError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let result = self.unit();
+        if result.is_err() {
+            self.failed = true;
+        }
+        result.transpose()
+    }
+}
+
+impl<'a> Units<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+    fn unit(&mut self) -> Result<Option<Unit<'a>>, ParseError> {
+        loop {
+            match self.peek() {
+                None => return Ok(None),
+                Some(b'\n') => {
+                    self.pos += 1;
+                    self.path.clear();
+                }
+                Some(b) if b <= b' ' => self.pos += 1,
+                Some(_) => break,
+            }
+        }
+        let position = self.pos;
+        let (mut header, absolute) = self.header()?;
+        match self.peek() {
+            None | Some(b';') | Some(b'\n') => (),
+            // IEEE 488.2: 7.4.3 - <PROGRAM HEADER SEPARATOR>
+            Some(b) if b <= b' ' => {
+                while self.peek().is_some_and(|b| b <= b' ' && b != b'\n') {
+                    self.pos += 1;
+                }
+            }
+            Some(_) => return Err(ParseError::Header { position: self.pos }),
+        }
+        let start = self.pos;
+        let (end, data_end) = unit_end(self.input, start)?;
+        self.pos = end;
+        if !header.common {
+            if !absolute {
+                let mut nodes = self.path.clone();
+                nodes.append(&mut header.nodes);
+                header.nodes = nodes;
+            }
+            self.path = header.nodes[..header.nodes.len() - 1].to_vec();
+        }
+        match self.peek() {
+            Some(b';') => self.pos += 1,
+            Some(b'\n') => {
+                self.pos += 1;
+                self.path.clear();
+            }
+            _ => (),
+        }
+        Ok(Some(Unit {
+            header,
+            position,
+            input: self.input,
+            start,
+            end: data_end,
+        }))
+    }
+    fn header(&mut self) -> Result<(ProgramHeader<'a>, bool), ParseError> {
+        let common = self.peek() == Some(b'*');
+        let absolute = self.peek() == Some(b':');
+        if common || absolute {
+            self.pos += 1;
+        }
+        let mut nodes = vec![self.mnemonic(common)?];
+        while !common && self.peek() == Some(b':') {
+            self.pos += 1;
+            nodes.push(self.mnemonic(false)?);
+        }
+        let query = self.peek() == Some(b'?');
+        if query {
+            self.pos += 1;
+        }
+        let header = ProgramHeader {
+            common,
+            nodes,
+            query,
+        };
+        Ok((header, absolute))
+    }
+    fn mnemonic(&mut self, common: bool) -> Result<Mnemonic<'a>, ParseError> {
+        let start = self.pos;
+        if !self.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            return Err(ParseError::Header { position: start });
+        }
+        while self
+            .peek()
+            .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_')
+        {
+            self.pos += 1;
+        }
+        let text = &self.input[start..self.pos];
+        let name_len = if common {
+            text.len()
+        } else {
+            text.len() - text.iter().rev().take_while(|b| b.is_ascii_digit()).count()
+        };
+        if name_len > MAX_MNEMONIC_LEN {
+            return Err(ParseError::MnemonicTooLong { position: start });
+        }
+        // Only ASCII alphanumeric characters and underscores were accepted
+        let text = str::from_utf8(text).unwrap_or_default();
+        let suffix = if name_len < text.len() {
+            let suffix = text[name_len..]
+                .parse()
+                .map_err(|_| ParseError::HeaderSuffix {
+                    position: start + name_len,
+                })?;
+            Some(suffix)
+        } else {
+            None
+        };
+        Ok(Mnemonic {
+            name: &text[..name_len],
+            suffix,
+        })
+    }
+}
+
+/// Finds the end of a message unit and the end of its program data, excluding trailing white space
+fn unit_end(input: &[u8], mut pos: usize) -> Result<(usize, usize), ParseError> {
+    let mut data_end = pos;
+    while let Some(&b) = input.get(pos) {
+        match b {
+            b';' | b'\n' => return Ok((pos, data_end)),
+            b'"' | b'\'' => {
+                let start = pos;
+                pos += 1;
+                loop {
+                    match input.get(pos) {
+                        None => {
+                            return Err(ParseError::Syntax {
+                                expected: "string program data",
+                                position: start,
+                            })
+                        }
+                        // Escaped quotes are handled as two consecutive strings
+                        Some(&ch) if ch == b => {
+                            pos += 1;
+                            break;
+                        }
+                        Some(_) => pos += 1,
+                    }
+                }
+            }
+            b'(' => {
+                let start = pos;
+                let mut depth = 0;
+                loop {
+                    match input.get(pos) {
+                        None | Some(b'\n') => {
+                            return Err(ParseError::Syntax {
+                                expected: "expression program data",
+                                position: start,
+                            })
+                        }
+                        Some(b'(') => depth += 1,
+                        Some(b')') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                pos += 1;
+                                break;
+                            }
+                        }
+                        Some(_) => (),
+                    }
+                    pos += 1;
+                }
+            }
+            b'#' => match input.get(pos + 1) {
+                // Indefinite length block extends to the terminator of the
+                // current message. The END message can't be seen in a byte
+                // stream, so the first newline ends the block
+                Some(b'0') => {
+                    let end = input[pos..]
+                        .iter()
+                        .position(|&b| b == b'\n')
+                        .map_or(input.len(), |len| pos + len);
+                    return Ok((end, end));
+                }
+                Some(&digits @ b'1'..=b'9') => {
+                    let len_start = pos + 2;
+                    let len_end = len_start + usize::from(digits - b'0');
+                    pos = input
+                        .get(len_start..len_end)
+                        .and_then(|len| str::from_utf8(len).ok())
+                        .and_then(|len| len.parse::<usize>().ok())
+                        .and_then(|len| len_end.checked_add(len))
+                        .filter(|&end| end <= input.len())
+                        .ok_or(ParseError::Syntax {
+                            expected: "block program data",
+                            position: pos,
+                        })?;
+                }
+                _ => pos += 1,
+            },
+            b if b <= b' ' => {
+                pos += 1;
+                continue;
+            }
+            _ => pos += 1,
+        }
+        data_end = pos;
+    }
+    Ok((pos, data_end))
+}
+
+/// Trait for types that can be parsed from SCPI program data
+pub trait FromProgram<'a>: Sized {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError>;
+}
+
+/// Cursor over the program data of a single message unit
+///
+/// Reference: IEEE 488.2: 7.7 - <PROGRAM DATA>
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    /// Returns the byte offset of the next unparsed data in the program message
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// Returns the data that hasn't been parsed yet
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.input[self.pos..self.end]
+    }
+    /// Parses the next value
+    pub fn parse<T: FromProgram<'a>>(&mut self) -> Result<T, ParseError> {
+        T::from_program(self)
+    }
+    /// Returns true if there's no more program data in the message unit
+    pub fn at_unit_end(&mut self) -> bool {
+        self.skip_whitespace();
+        self.pos >= self.end
+    }
+    /// Consumes a program data separator (`,`)
+    ///
+    /// Reference: IEEE 488.2: 7.4.2 - <PROGRAM DATA SEPARATOR>
+    pub fn data_separator(&mut self) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.pos >= self.end {
+            return Err(ParseError::MissingParameter { position: self.pos });
+        }
+        if self.peek() != Some(b',') {
+            return Err(ParseError::Syntax {
+                expected: "program data separator",
+                position: self.pos,
+            });
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    /// Checks that no program data is left
+    pub fn finish(mut self) -> Result<(), ParseError> {
+        if self.at_unit_end() {
+            Ok(())
+        } else {
+            Err(ParseError::ParameterNotAllowed { position: self.pos })
+        }
+    }
+    /// Parses character program data
+    ///
+    /// Reference: IEEE 488.2: 7.7.1 - <CHARACTER PROGRAM DATA>
+    pub fn character(&mut self) -> Result<&'a str, ParseError> {
+        const EXPECTED: &str = "character program data";
+        self.start(EXPECTED, |b| b.is_ascii_alphabetic())?;
+        let start = self.pos;
+        self.skip_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if self.pos - start > MAX_MNEMONIC_LEN {
+            return Err(ParseError::Syntax {
+                expected: EXPECTED,
+                position: start,
+            });
+        }
+        Ok(self.ascii(start))
+    }
+    /// Parses decimal numeric program data, returning its textual representation
+    ///
+    /// Reference: IEEE 488.2: 7.7.2 - <DECIMAL NUMERIC PROGRAM DATA>
+    pub fn decimal(&mut self) -> Result<&'a str, ParseError> {
+        const EXPECTED: &str = "decimal numeric program data";
+        self.start(EXPECTED, |b| {
+            b.is_ascii_digit() || b == b'+' || b == b'-' || b == b'.'
+        })?;
+        let start = self.pos;
+        if let Some(b'+') | Some(b'-') = self.peek() {
+            self.pos += 1;
+        }
+        let mut digits = self.skip_while(|b| b.is_ascii_digit());
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            digits += self.skip_while(|b| b.is_ascii_digit());
+        }
+        if digits == 0 {
+            return Err(ParseError::Syntax {
+                expected: EXPECTED,
+                position: start,
+            });
+        }
+        if let Some(b'E') | Some(b'e') = self.peek() {
+            let mantissa_end = self.pos;
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            // Without exponent digits, the letter starts a suffix instead (e.g. `1EV`)
+            if self.skip_while(|b| b.is_ascii_digit()) == 0 {
+                self.pos = mantissa_end;
+            }
+        }
+        Ok(self.ascii(start))
+    }
+    /// Parses an optional suffix after decimal numeric program data
+    ///
+    /// Reference: IEEE 488.2: 7.7.3 - <SUFFIX PROGRAM DATA>
+    pub fn suffix(&mut self) -> Option<&'a str> {
+        let start = self.pos;
+        self.skip_whitespace();
+        match self.peek() {
+            Some(b) if self.pos < self.end && (b.is_ascii_alphabetic() || b == b'/') => {
+                let suffix_start = self.pos;
+                self.skip_while(|b| b.is_ascii_alphanumeric() || b"/.-".contains(&b));
+                Some(self.ascii(suffix_start))
+            }
+            _ => {
+                self.pos = start;
+                None
+            }
+        }
+    }
+    /// Parses hexadecimal, octal or binary numeric program data
+    ///
+    /// Reference: IEEE 488.2: 7.7.4 - <NONDECIMAL NUMERIC PROGRAM DATA>
+    pub fn non_decimal(&mut self) -> Result<u64, ParseError> {
+        const EXPECTED: &str = "non-decimal numeric program data";
+        self.start(EXPECTED, |b| b == b'#')?;
+        let start = self.pos;
+        self.pos += 1;
+        let radix = match self.peek() {
+            Some(b'H') | Some(b'h') => 16,
+            Some(b'Q') | Some(b'q') => 8,
+            Some(b'B') | Some(b'b') => 2,
+            _ => {
+                return Err(ParseError::DataType {
+                    expected: EXPECTED,
+                    position: start,
+                })
+            }
+        };
+        self.pos += 1;
+        let digits_start = self.pos;
+        if self.skip_while(|b| (b as char).is_digit(radix)) == 0 {
+            return Err(ParseError::Syntax {
+                expected: EXPECTED,
+                position: start,
+            });
+        }
+        u64::from_str_radix(self.ascii(digits_start), radix)
+            .map_err(|_| ParseError::OutOfRange { position: start })
+    }
+    /// Parses string program data delimited by either quote character
+    ///
+    /// Reference: IEEE 488.2: 7.7.5 - <STRING PROGRAM DATA>
+    pub fn string(&mut self) -> Result<String, ParseError> {
+        const EXPECTED: &str = "string program data";
+        self.start(EXPECTED, |b| b == b'"' || b == b'\'')?;
+        let start = self.pos;
+        let quote = self.input[start];
+        self.pos += 1;
+        let mut result = Vec::new();
+        loop {
+            match self.peek() {
+                Some(b) if b == quote => {
+                    self.pos += 1;
+                    // Quotes are escaped by duplicating them
+                    if self.peek() == Some(quote) {
+                        self.pos += 1;
+                        result.push(quote);
+                    } else {
+                        break;
+                    }
+                }
+                Some(b) if self.pos < self.end => {
+                    self.pos += 1;
+                    result.push(b);
+                }
+                _ => {
+                    return Err(ParseError::Syntax {
+                        expected: EXPECTED,
+                        position: start,
+                    })
+                }
+            }
+        }
+        String::from_utf8(result).map_err(|_| ParseError::Syntax {
+            expected: EXPECTED,
+            position: start,
+        })
+    }
+    /// Parses definite or indefinite length arbitrary block program data
+    ///
+    /// Reference: IEEE 488.2: 7.7.6 - <ARBITRARY BLOCK PROGRAM DATA>
+    pub fn block(&mut self) -> Result<&'a [u8], ParseError> {
+        const EXPECTED: &str = "block program data";
+        self.start(EXPECTED, |b| b == b'#')?;
+        let start = self.pos;
+        let digits = match self.input.get(start + 1) {
+            Some(&b @ b'0'..=b'9') => usize::from(b - b'0'),
+            _ => {
+                return Err(ParseError::DataType {
+                    expected: EXPECTED,
+                    position: start,
+                })
+            }
+        };
+        self.pos += 2;
+        if digits == 0 {
+            let data = &self.input[self.pos..self.end];
+            self.pos = self.end;
+            return Ok(data);
+        }
+        let len_start = self.pos;
+        if self.skip_while(|b| b.is_ascii_digit()) < digits {
+            return Err(ParseError::Syntax {
+                expected: EXPECTED,
+                position: start,
+            });
+        }
+        self.pos = len_start + digits;
+        let len = self
+            .ascii(len_start)
+            .parse::<usize>()
+            .map_err(|_| ParseError::OutOfRange { position: start })?;
+        match self.pos.checked_add(len) {
+            Some(end) if end <= self.end => {
+                let data = &self.input[self.pos..end];
+                self.pos = end;
+                Ok(data)
+            }
+            _ => Err(ParseError::Syntax {
+                expected: EXPECTED,
+                position: start,
+            }),
+        }
+    }
+    /// Parses expression program data, returning it including the parentheses
+    ///
+    /// Reference: IEEE 488.2: 7.7.7 - <EXPRESSION PROGRAM DATA>
+    pub fn expression(&mut self) -> Result<&'a str, ParseError> {
+        const EXPECTED: &str = "expression program data";
+        self.start(EXPECTED, |b| b == b'(')?;
+        let start = self.pos;
+        let mut depth = 0;
+        while self.pos < self.end {
+            let b = self.input[self.pos];
+            self.pos += 1;
+            match b {
+                b'(' => depth += 1,
+                b')' if depth == 1 => return Ok(self.ascii(start)),
+                b')' => depth -= 1,
+                _ => (),
+            }
+        }
+        Err(ParseError::Syntax {
+            expected: EXPECTED,
+            position: start,
+        })
+    }
+    fn peek(&self) -> Option<u8> {
+        if self.pos < self.end {
+            self.input.get(self.pos).copied()
+        } else {
+            None
+        }
+    }
+    fn skip_while<F: Fn(u8) -> bool>(&mut self, f: F) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+    fn skip_whitespace(&mut self) {
+        // IEEE 488.2: 7.4.1.2 - <white space>
+        self.skip_while(|b| b <= b' ');
+    }
+    /// Checks that a data element of the expected type starts at the current position
+    fn start<F: Fn(u8) -> bool>(&mut self, expected: &'static str, f: F) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            None => Err(ParseError::MissingParameter { position: self.pos }),
+            Some(b',') => Err(ParseError::MissingParameter { position: self.pos }),
+            Some(b) if f(b) => Ok(()),
+            Some(_) => Err(ParseError::DataType {
+                expected,
+                position: self.pos,
+            }),
+        }
+    }
+    /// Returns data that was already checked to be ASCII
+    fn ascii(&self, start: usize) -> &'a str {
+        str::from_utf8(&self.input[start..self.pos]).unwrap_or_default()
+    }
+    fn no_suffix(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        match self.suffix() {
+            Some(_) => Err(ParseError::SuffixNotAllowed { position: start }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+fn parse<'a, T: FromProgram<'a>>(data: &'a [u8]) -> Result<T, ParseError> {
+    units(data).next().unwrap()?.parse()
+}
+
+impl<'a> FromProgram<'a> for Discrete<'a> {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        p.character().map(Discrete)
+    }
+}
+
+/// Parses character program data that matches one of the given short/long forms
+fn keyword<'a, T: Copy>(p: &mut Parser<'a>, keywords: &[(&str, &str, T)]) -> Result<T, ParseError> {
+    p.skip_whitespace();
+    let start = p.position();
+    let text = p.character()?;
+    keywords
+        .iter()
+        .find(|(short, long, _)| {
+            text.eq_ignore_ascii_case(short) || text.eq_ignore_ascii_case(long)
+        })
+        .map(|&(_, _, value)| value)
+        .ok_or(ParseError::IllegalValue { position: start })
+}
+
+impl<'a> FromProgram<'a> for DefaultValue {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        keyword(p, &[("DEF", "DEFAULT", DefaultValue)])
+    }
+}
+
+impl<'a> FromProgram<'a> for Limit {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        keyword(
+            p,
+            &[
+                ("MIN", "MINIMUM", Limit::Min),
+                ("MAX", "MAXIMUM", Limit::Max),
+            ],
+        )
+    }
+}
+
+impl<'a> FromProgram<'a> for Step {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        keyword(p, &[("UP", "UP", Step::Up), ("DOWN", "DOWN", Step::Down)])
+    }
+}
+
+#[test]
+fn test_keyword_program_data() {
+    assert_eq!(parse(b"VOLT DEFault"), Ok(DefaultValue));
+    assert_eq!(parse(b"VOLT max"), Ok(Limit::Max));
+    assert_eq!(parse(b"VOLT MINimum"), Ok(Limit::Min));
+    assert_eq!(parse(b"VOLT DOWN\n"), Ok(Step::Down));
+    assert_eq!(parse(b"TRIG:SOUR BUS"), Ok(Discrete("BUS")));
+    assert_eq!(
+        parse::<Limit>(b"VOLT MINI"),
+        Err(ParseError::IllegalValue { position: 5 })
+    );
+    assert_eq!(
+        parse::<Limit>(b"VOLT 5"),
+        Err(ParseError::DataType {
+            expected: "character program data",
+            position: 5
+        })
+    );
+    assert_eq!(
+        parse::<Limit>(b"VOLT"),
+        Err(ParseError::MissingParameter { position: 4 })
+    );
+}
+
+/// Numeric parameter that also accepts the special SCPI values
+///
+/// Reference: SCPI 1999.0: 7.2.1 - Numeric Values
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NumericValue<T> {
+    Value(T),
+    Default,
+    Limit(Limit),
+    Step(Step),
+}
+
+impl<'a, T: FromProgram<'a>> FromProgram<'a> for NumericValue<T> {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        p.skip_whitespace();
+        if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            let mut keyword_parser = p.clone();
+            let keyword = keyword(
+                &mut keyword_parser,
+                &[
+                    ("DEF", "DEFAULT", None),
+                    ("MIN", "MINIMUM", Some(Ok(Limit::Min))),
+                    ("MAX", "MAXIMUM", Some(Ok(Limit::Max))),
+                    ("UP", "UP", Some(Err(Step::Up))),
+                    ("DOWN", "DOWN", Some(Err(Step::Down))),
+                ],
+            );
+            if let Ok(keyword) = keyword {
+                *p = keyword_parser;
+                return Ok(match keyword {
+                    None => NumericValue::Default,
+                    Some(Ok(limit)) => NumericValue::Limit(limit),
+                    Some(Err(step)) => NumericValue::Step(step),
+                });
+            }
+        }
+        T::from_program(p).map(NumericValue::Value)
+    }
+}
+
+#[test]
+fn test_numeric_value_program_data() {
+    assert_eq!(parse(b"VOLT 1.5"), Ok(NumericValue::Value(1.5)));
+    assert_eq!(
+        parse::<NumericValue<f64>>(b"VOLT MAX"),
+        Ok(NumericValue::Limit(Limit::Max))
+    );
+    assert_eq!(
+        parse::<NumericValue<u32>>(b"VOLT DEF"),
+        Ok(NumericValue::Default)
+    );
+    assert_eq!(
+        parse::<NumericValue<u32>>(b"VOLT UP"),
+        Ok(NumericValue::Step(Step::Up))
+    );
+    assert_eq!(
+        parse::<NumericValue<f64>>(b"VOLT INF"),
+        Ok(NumericValue::Value(f64::INFINITY))
+    );
+    assert_eq!(
+        parse::<NumericValue<f64>>(b"VOLT HIGH"),
+        Err(ParseError::IllegalValue { position: 5 })
+    );
+}
+
+impl<'a> FromProgram<'a> for String {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        p.string()
+    }
+}
+
+#[test]
+fn test_string_program_data() {
+    assert_eq!(parse(b"DISP:TEXT \"Hello\""), Ok("Hello".to_owned()));
+    assert_eq!(
+        parse(b"DISP:TEXT 'it''s \"quoted\"'\n"),
+        Ok("it's \"quoted\"".to_owned())
+    );
+    assert_eq!(parse(b"DISP:TEXT \"a;b\""), Ok("a;b".to_owned()));
+    assert_eq!(
+        units(b"DISP:TEXT \"abc").next(),
+        Some(Err(ParseError::Syntax {
+            expected: "string program data",
+            position: 10
+        }))
+    );
+}
+
+impl<'a> FromProgram<'a> for Block<'a> {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        p.block().map(Block)
+    }
+}
+
+#[test]
+fn test_block_program_data() {
+    assert_eq!(parse(b"DATA #15;\n\x01;\n"), Ok(Block(b";\n\x01;\n")));
+    assert_eq!(parse(b"DATA #0a;b\n"), Ok(Block(b"a;b")));
+    let mut iter = units(b"DATA #0a;b\n*RST\n");
+    assert_eq!(iter.next().unwrap().unwrap().parse(), Ok(Block(b"a;b")));
+    assert!(iter.next().unwrap().unwrap().header.common);
+    assert!(iter.next().is_none());
+    assert_eq!(parse(b"DATA #210abcdefghij"), Ok(Block(b"abcdefghij")));
+    assert_eq!(
+        units(b"DATA #15abc").next(),
+        Some(Err(ParseError::Syntax {
+            expected: "block program data",
+            position: 5
+        }))
+    );
+}
+
+impl<'a> FromProgram<'a> for ChannelList {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        let start = p.position();
+        let expression = p.expression()?;
+        // Channel lists use the same syntax in program and response data
+        response::parse(expression.as_bytes()).map_err(|err| match err {
+            DecodeError::Syntax { position, .. } => ParseError::Syntax {
+                expected: "channel list",
+                position: start + position,
+            },
+            DecodeError::OutOfRange { position } => ParseError::OutOfRange {
+                position: start + position,
+            },
+            _ => ParseError::Syntax {
+                expected: "channel list",
+                position: start,
+            },
+        })
+    }
+}
+
+#[test]
+fn test_channel_list_program_data() {
+    assert_eq!(
+        parse(b"ROUT:CLOS (@1,3:5)"),
+        Ok(ChannelList::new().channel(1).range(3, 5))
+    );
+    assert_eq!(
+        parse(b"ROUT:CLOS (@CARD1(1),2)"),
+        Ok(ChannelList::new()
+            .module("CARD1", ChannelList::new().channel(1))
+            .channel(2))
+    );
+    assert_eq!(
+        parse::<ChannelList>(b"ROUT:CLOS (1,2)"),
+        Err(ParseError::Syntax {
+            expected: "channel list",
+            position: 11
+        })
+    );
+}
+
+impl<'a> FromProgram<'a> for bool {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        // SCPI 1999.0: 7.3 - Boolean Program Data
+        p.skip_whitespace();
+        if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            keyword(p, &[("ON", "ON", true), ("OFF", "OFF", false)])
+        } else {
+            f64::from_program(p).map(|value| value.round() != 0.0)
+        }
+    }
+}
+
+#[test]
+fn test_bool_program_data() {
+    assert_eq!(parse(b"OUTP ON"), Ok(true));
+    assert_eq!(parse(b"OUTP off"), Ok(false));
+    assert_eq!(parse(b"OUTP 0.7"), Ok(true));
+    assert_eq!(parse(b"OUTP 0"), Ok(false));
+    assert_eq!(
+        parse::<bool>(b"OUTP YES"),
+        Err(ParseError::IllegalValue { position: 5 })
+    );
+}
+
+macro_rules! impl_float_program {
+    ($($ty:ident),*) => {
+        $(
+            impl<'a> FromProgram<'a> for $ty {
+                fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+                    p.skip_whitespace();
+                    if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+                        // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+                        // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
+                        return keyword(
+                            p,
+                            &[
+                                ("NAN", "NAN", $ty::NAN),
+                                ("INF", "INFINITY", $ty::INFINITY),
+                                ("NINF", "NINFINITY", $ty::NEG_INFINITY),
+                            ],
+                        );
+                    }
+                    let start = p.position();
+                    let text = p.decimal()?;
+                    p.no_suffix()?;
+                    match text.parse::<$ty>() {
+                        Ok(value) if value.is_finite() => Ok(value),
+                        _ => Err(ParseError::OutOfRange { position: start }),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float_program!(f32, f64);
+
+#[test]
+fn test_float_program_data() {
+    assert_eq!(parse(b"VOLT 1"), Ok(1.0));
+    assert_eq!(parse(b"VOLT -12.5"), Ok(-12.5));
+    assert_eq!(parse(b"VOLT .5e-3"), Ok(0.5e-3));
+    assert_eq!(parse(b"VOLT +1.5E+3\n"), Ok(1500.0));
+    assert!(parse::<f64>(b"VOLT NAN").unwrap().is_nan());
+    assert_eq!(parse(b"VOLT NINF"), Ok(f32::NEG_INFINITY));
+    assert_eq!(
+        parse::<f32>(b"VOLT 1E39"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+    assert_eq!(
+        parse::<f64>(b"VOLT 5 mV"),
+        Err(ParseError::SuffixNotAllowed { position: 6 })
+    );
+    assert_eq!(
+        parse::<f64>(b"VOLT ."),
+        Err(ParseError::Syntax {
+            expected: "decimal numeric program data",
+            position: 5
+        })
+    );
+    assert_eq!(
+        parse::<f64>(b"VOLT \"5\""),
+        Err(ParseError::DataType {
+            expected: "decimal numeric program data",
+            position: 5
+        })
+    );
+}
+
+#[test]
+fn test_suffix_program_data() {
+    let unit = units(b"FREQ 1.5 MHZ,2EV, 3").next().unwrap().unwrap();
+    let mut p = unit.params();
+    assert_eq!(p.decimal(), Ok("1.5"));
+    assert_eq!(p.suffix(), Some("MHZ"));
+    p.data_separator().unwrap();
+    assert_eq!(p.decimal(), Ok("2"));
+    assert_eq!(p.suffix(), Some("EV"));
+    p.data_separator().unwrap();
+    assert_eq!(p.decimal(), Ok("3"));
+    assert_eq!(p.suffix(), None);
+    p.finish().unwrap();
+}
+
+macro_rules! impl_integer_program {
+    ($($ty:ident),*) => {
+        $(
+            impl<'a> FromProgram<'a> for $ty {
+                fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+                    p.skip_whitespace();
+                    let start = p.position();
+                    let out_of_range = ParseError::OutOfRange { position: start };
+                    if p.peek() == Some(b'#') {
+                        let value = p.non_decimal()?;
+                        return $ty::try_from(value).map_err(|_| out_of_range);
+                    }
+                    let text = p.decimal()?;
+                    p.no_suffix()?;
+                    if text.contains(|ch| ch == '.' || ch == 'e' || ch == 'E') {
+                        // IEEE 488.2: 7.7.2.4 - decimal numeric data is rounded to an integer
+                        let value = text.parse::<f64>().map_err(|_| out_of_range.clone())?.round();
+                        // MAX rounds up when converted to f64, so the exclusive
+                        // power of two above it is used as the upper bound
+                        let limit = ($ty::MAX / 2 + 1) as f64 * 2.0;
+                        if value >= $ty::MIN as f64 && value < limit {
+                            Ok(value as $ty)
+                        } else {
+                            Err(out_of_range)
+                        }
+                    } else {
+                        text.trim_start_matches('+').parse().map_err(|_| out_of_range)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_program!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[test]
+fn test_integer_program_data() {
+    assert_eq!(parse(b"*ESE 32"), Ok(32u8));
+    assert_eq!(parse(b"*ESE +32"), Ok(32u8));
+    assert_eq!(parse(b"COUN -5"), Ok(-5i32));
+    assert_eq!(parse(b"COUN 2.5E1"), Ok(25u16));
+    assert_eq!(parse(b"COUN 1.6"), Ok(2i8));
+    assert_eq!(parse(b"*ESE #H1F"), Ok(31u8));
+    assert_eq!(parse(b"*ESE #b101"), Ok(5u32));
+    assert_eq!(
+        parse::<u8>(b"*ESE 256"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+    assert_eq!(
+        parse::<u8>(b"*ESE -1"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+    assert_eq!(
+        parse::<u64>(b"VOLT 1.8446744073709551616E19"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+    assert_eq!(
+        parse::<i64>(b"VOLT 9.223372036854775808E18"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+    assert_eq!(parse(b"VOLT -9.223372036854775808E18"), Ok(i64::MIN));
+    assert_eq!(
+        parse::<u8>(b"*ESE #Q400"),
+        Err(ParseError::OutOfRange { position: 5 })
+    );
+}
+
+macro_rules! impl_non_decimal_program {
+    ($($wrapper:ident),*) => {
+        $(
+            impl<'a, T: FromProgram<'a>> FromProgram<'a> for $wrapper<T> {
+                fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+                    T::from_program(p).map($wrapper)
+                }
+            }
+        )*
+    };
+}
+
+impl_non_decimal_program!(Hex, Octal, Binary);
+
+impl<'a, T: FromProgram<'a>> FromProgram<'a> for Option<T> {
+    /// Parses an optional parameter, which is absent at the end of the message unit
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        if p.at_unit_end() {
+            Ok(None)
+        } else {
+            T::from_program(p).map(Some)
+        }
+    }
+}
+
+impl<'a, T: FromProgram<'a>> FromProgram<'a> for Vec<T> {
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        let mut values = Vec::new();
+        if p.at_unit_end() {
+            return Ok(values);
+        }
+        loop {
+            values.push(T::from_program(p)?);
+            if p.at_unit_end() {
+                return Ok(values);
+            }
+            p.data_separator()?;
+        }
+    }
+}
+
+impl<'a> FromProgram<'a> for () {
+    fn from_program(_: &mut Parser<'a>) -> Result<Self, ParseError> {
+        Ok(())
+    }
+}
+
+impl<'a, A, B> FromProgram<'a> for (A, B)
+where
+    A: FromProgram<'a>,
+    B: FromProgram<'a>,
+{
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        Ok((a, b))
+    }
+}
+
+impl<'a, A, B, C> FromProgram<'a> for (A, B, C)
+where
+    A: FromProgram<'a>,
+    B: FromProgram<'a>,
+    C: FromProgram<'a>,
+{
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        p.data_separator()?;
+        let c = p.parse()?;
+        Ok((a, b, c))
+    }
+}
+
+impl<'a, A, B, C, D> FromProgram<'a> for (A, B, C, D)
+where
+    A: FromProgram<'a>,
+    B: FromProgram<'a>,
+    C: FromProgram<'a>,
+    D: FromProgram<'a>,
+{
+    fn from_program(p: &mut Parser<'a>) -> Result<Self, ParseError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        p.data_separator()?;
+        let c = p.parse()?;
+        p.data_separator()?;
+        let d = p.parse()?;
+        Ok((a, b, c, d))
+    }
+}
+
+#[test]
+fn test_multiple_program_data() {
+    assert_eq!(parse(b"APPL 1, 2.5 ,ON"), Ok((1u32, 2.5f64, true)));
+    assert_eq!(parse(b"DATA 1,2,3"), Ok(vec![1u8, 2, 3]));
+    assert_eq!(parse(b"DATA"), Ok(Vec::<u8>::new()));
+    assert_eq!(parse(b"MEAS:VOLT?"), Ok(None::<f64>));
+    assert_eq!(parse(b"MEAS:VOLT? 10"), Ok(Some(10.0)));
+    assert_eq!(
+        parse::<(u8, u8)>(b"APPL 1"),
+        Err(ParseError::MissingParameter { position: 6 })
+    );
+    assert_eq!(
+        parse::<(u8, u8)>(b"APPL 1,,2"),
+        Err(ParseError::MissingParameter { position: 7 })
+    );
+    assert_eq!(
+        parse::<u8>(b"APPL 1,2"),
+        Err(ParseError::ParameterNotAllowed { position: 6 })
+    );
+    assert_eq!(
+        parse::<()>(b"*RST 1"),
+        Err(ParseError::ParameterNotAllowed { position: 5 })
+    );
+    assert_eq!(
+        parse::<(u8, u8)>(b"APPL 1 2"),
+        Err(ParseError::Syntax {
+            expected: "program data separator",
+            position: 7
+        })
+    );
+}
+
+#[cfg(test)]
+fn mnemonics<'a>(nodes: &[(&'a str, Option<u32>)]) -> Vec<Mnemonic<'a>> {
+    nodes
+        .iter()
+        .map(|&(name, suffix)| Mnemonic { name, suffix })
+        .collect()
+}
+
+#[test]
+fn test_units() {
+    let input = b"*CLS;:SOUR:VOLT 5;CURR 1;*OPC;FREQ?;:OUTP1:STAT ON\nVOLT?\n";
+    let headers: Vec<_> = units(input).map(|unit| unit.unwrap().header).collect();
+    let header = |common, nodes: &[(&'static str, Option<u32>)], query| ProgramHeader {
+        common,
+        nodes: mnemonics(nodes),
+        query,
+    };
+    assert_eq!(
+        headers,
+        vec![
+            header(true, &[("CLS", None)], false),
+            header(false, &[("SOUR", None), ("VOLT", None)], false),
+            header(false, &[("SOUR", None), ("CURR", None)], false),
+            // Common commands don't change the current path
+            header(true, &[("OPC", None)], false),
+            header(false, &[("SOUR", None), ("FREQ", None)], true),
+            header(false, &[("OUTP", Some(1)), ("STAT", None)], false),
+            // The message terminator resets the path
+            header(false, &[("VOLT", None)], true),
+        ]
+    );
+}
+
+#[test]
+fn test_unit_params() {
+    let input = b"SOUR:LIST:VOLT 1,2,3;  :TRIG;DISP:TEXT 'a;b' ;\n";
+    let units: Vec<_> = units(input).map(Result::unwrap).collect();
+    assert_eq!(units.len(), 3);
+    assert_eq!(units[0].parse(), Ok(vec![1.0, 2.0, 3.0]));
+    assert_eq!(units[0].position, 0);
+    assert!(!units[1].has_params());
+    assert_eq!(units[1].position, 23);
+    assert_eq!(units[2].params().remaining(), b"'a;b'");
+}
+
+#[test]
+fn test_unit_errors() {
+    assert_eq!(
+        units(b"VOLT 1;2VOLT").collect::<Vec<_>>()[1],
+        Err(ParseError::Header { position: 7 })
+    );
+    assert_eq!(
+        units(b"VOLTAGEVOLTAGE 1").next(),
+        Some(Err(ParseError::MnemonicTooLong { position: 0 }))
+    );
+    assert_eq!(
+        units(b"OUTP99999999999").next(),
+        Some(Err(ParseError::HeaderSuffix { position: 4 }))
+    );
+    assert_eq!(
+        units(b"VOLT,5").next(),
+        Some(Err(ParseError::Header { position: 4 }))
+    );
+    // Parsing stops after the first error
+    assert_eq!(units(b"VOLT:;CURR 1").count(), 1);
+    assert_eq!(units(b"\n \n").count(), 0);
+}
+
+#[test]
+fn test_mnemonic_matches() {
+    let volt = Mnemonic {
+        name: "volt",
+        suffix: None,
+    };
+    assert!(volt.matches("VOLTage"));
+    assert!(!volt.matches("CURRent"));
+    let voltage = Mnemonic {
+        name: "VOLTAGE",
+        suffix: None,
+    };
+    assert!(voltage.matches("VOLTage"));
+    let volta = Mnemonic {
+        name: "VOLTA",
+        suffix: None,
+    };
+    assert!(!volta.matches("VOLTage"));
+    let idn = Mnemonic {
+        name: "idn",
+        suffix: None,
+    };
+    assert!(idn.matches("IDN"));
+}
@@ -0,0 +1,854 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{convert::TryFrom, str};
+
+use crate::{
+    Binary, Block, Channel, ChannelEntry, ChannelList, DecodeError, DefaultValue, Discrete, Hex,
+    Limit, Octal, Step,
+};
+
+/// Decodes a complete response message
+///
+/// A trailing response message terminator is accepted but not required.
+pub fn parse<'a, T: FromResponse<'a>>(input: &'a [u8]) -> Result<T, DecodeError> {
+    let mut parser = Parser::new(input);
+    let value = parser.parse()?;
+    parser.finish()?;
+    Ok(value)
+}
+
+/// Trait for types that can be decoded from SCPI response data
+pub trait FromResponse<'a>: Sized {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError>;
+}
+
+/// Cursor over a response message
+///
+/// Reference: IEEE 488.2: 8 - Device Talking Elements
+#[derive(Clone, Debug)]
+pub struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a [u8]) -> Parser<'a> {
+        Parser { input, pos: 0 }
+    }
+    /// Returns the byte offset of the next unparsed data
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+    /// Returns the data that hasn't been parsed yet
+    pub fn remaining(&self) -> &'a [u8] {
+        &self.input[self.pos..]
+    }
+    /// Decodes the next value
+    pub fn parse<T: FromResponse<'a>>(&mut self) -> Result<T, DecodeError> {
+        T::from_response(self)
+    }
+    /// Returns true if there's no more data in the current response message unit
+    pub fn at_unit_end(&mut self) -> bool {
+        self.skip_whitespace();
+        matches!(self.peek(), None | Some(b';') | Some(b'\n'))
+    }
+    /// Consumes a response data separator (`,`)
+    pub fn data_separator(&mut self) -> Result<(), DecodeError> {
+        self.expect(b',', "response data separator")
+    }
+    /// Consumes a response message unit separator (`;`)
+    pub fn unit_separator(&mut self) -> Result<(), DecodeError> {
+        self.expect(b';', "response message unit separator")
+    }
+    /// Consumes an optional response message terminator and checks that no data is left
+    pub fn finish(mut self) -> Result<(), DecodeError> {
+        self.skip_whitespace();
+        if self.peek() == Some(b'\n') {
+            self.pos += 1;
+        }
+        if self.pos < self.input.len() {
+            Err(DecodeError::TrailingData { position: self.pos })
+        } else {
+            Ok(())
+        }
+    }
+    /// Decodes character response data
+    ///
+    /// Reference: IEEE 488.2: 8.7.1 - <CHARACTER RESPONSE DATA>
+    pub fn character(&mut self) -> Result<&'a str, DecodeError> {
+        const EXPECTED: &str = "character response data";
+        self.skip_whitespace();
+        let start = self.pos;
+        match self.peek() {
+            Some(b) if b.is_ascii_alphabetic() => self.pos += 1,
+            _ => return Err(self.error(EXPECTED)),
+        }
+        self.skip_while(|b| b.is_ascii_alphanumeric() || b == b'_');
+        self.ascii(start)
+    }
+    /// Decodes NR1, NR2 or NR3 numeric response data, returning its textual representation
+    ///
+    /// Reference: IEEE 488.2: 8.7.2..8.7.4 - <NR1/NR2/NR3 NUMERIC RESPONSE DATA>
+    pub fn numeric(&mut self) -> Result<&'a str, DecodeError> {
+        const EXPECTED: &str = "numeric response data";
+        self.skip_whitespace();
+        let start = self.pos;
+        if let Some(b'+') | Some(b'-') = self.peek() {
+            self.pos += 1;
+        }
+        let mut digits = self.skip_while(|b| b.is_ascii_digit());
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            digits += self.skip_while(|b| b.is_ascii_digit());
+        }
+        if digits == 0 {
+            self.pos = start;
+            return Err(self.error(EXPECTED));
+        }
+        if let Some(b'E') | Some(b'e') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+') | Some(b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if self.skip_while(|b| b.is_ascii_digit()) == 0 {
+                return Err(self.error(EXPECTED));
+            }
+        }
+        self.ascii(start)
+    }
+    /// Decodes hexadecimal, octal or binary numeric response data
+    ///
+    /// Reference: IEEE 488.2: 8.7.5..8.7.7 - <HEXADECIMAL/OCTAL/BINARY NUMERIC RESPONSE DATA>
+    pub fn non_decimal(&mut self) -> Result<u64, DecodeError> {
+        const EXPECTED: &str = "non-decimal numeric response data";
+        self.skip_whitespace();
+        let start = self.pos;
+        if self.peek() != Some(b'#') {
+            return Err(self.error(EXPECTED));
+        }
+        self.pos += 1;
+        let radix = match self.peek() {
+            Some(b'H') | Some(b'h') => 16,
+            Some(b'Q') | Some(b'q') => 8,
+            Some(b'B') | Some(b'b') => 2,
+            _ => return Err(self.error(EXPECTED)),
+        };
+        self.pos += 1;
+        let digits_start = self.pos;
+        if self.skip_while(|b| (b as char).is_digit(radix)) == 0 {
+            return Err(self.error(EXPECTED));
+        }
+        let digits = self.ascii(digits_start)?;
+        u64::from_str_radix(digits, radix).map_err(|_| DecodeError::OutOfRange { position: start })
+    }
+    /// Decodes string response data
+    ///
+    /// Reference: IEEE 488.2: 8.7.8 - <STRING RESPONSE DATA>
+    pub fn string(&mut self) -> Result<String, DecodeError> {
+        const EXPECTED: &str = "string response data";
+        self.skip_whitespace();
+        let quote = match self.peek() {
+            Some(quote @ b'"') | Some(quote @ b'\'') => quote,
+            _ => return Err(self.error(EXPECTED)),
+        };
+        let start = self.pos;
+        self.pos += 1;
+        let mut result = Vec::new();
+        loop {
+            match self.peek() {
+                None => return Err(DecodeError::UnexpectedEnd { expected: EXPECTED }),
+                Some(b) if b == quote => {
+                    self.pos += 1;
+                    // Quotes are escaped by duplicating them
+                    if self.peek() == Some(quote) {
+                        self.pos += 1;
+                        result.push(quote);
+                    } else {
+                        break;
+                    }
+                }
+                Some(b) => {
+                    self.pos += 1;
+                    result.push(b);
+                }
+            }
+        }
+        String::from_utf8(result).map_err(|_| DecodeError::Syntax {
+            expected: EXPECTED,
+            position: start,
+        })
+    }
+    /// Decodes arbitrary ASCII response data, which extends to the end of the response message
+    ///
+    /// Reference: IEEE 488.2: 8.7.11 - <ARBITRARY ASCII RESPONSE DATA>
+    pub fn arbitrary_ascii(&mut self) -> Result<&'a str, DecodeError> {
+        let start = self.pos;
+        let mut end = self.input.len();
+        if self.input[start..].last() == Some(&b'\n') {
+            end -= 1;
+        }
+        self.pos = end;
+        self.ascii(start)
+    }
+    /// Decodes definite or indefinite length arbitrary block response data
+    ///
+    /// An indefinite length block extends to the end of the response message.
+    ///
+    /// Reference: IEEE 488.2: 8.7.9 - <DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA>,
+    /// 8.7.10 - <INDEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA>
+    pub fn block(&mut self) -> Result<&'a [u8], DecodeError> {
+        const EXPECTED: &str = "arbitrary block response data";
+        self.skip_whitespace();
+        if self.peek() != Some(b'#') {
+            return Err(self.error(EXPECTED));
+        }
+        self.pos += 1;
+        let digits = match self.peek() {
+            Some(b @ b'0'..=b'9') => usize::from(b - b'0'),
+            _ => return Err(self.error(EXPECTED)),
+        };
+        self.pos += 1;
+        if digits == 0 {
+            let mut end = self.input.len();
+            if self.input[self.pos..].last() == Some(&b'\n') {
+                end -= 1;
+            }
+            let data = &self.input[self.pos..end];
+            self.pos = end;
+            return Ok(data);
+        }
+        let start = self.pos;
+        if self.skip_while(|b| b.is_ascii_digit()) < digits {
+            self.pos = start;
+            return Err(self.error(EXPECTED));
+        }
+        self.pos = start + digits;
+        let len = self
+            .ascii(start)?
+            .parse::<usize>()
+            .map_err(|_| DecodeError::OutOfRange { position: start })?;
+        match self.pos.checked_add(len) {
+            Some(end) if end <= self.input.len() => {
+                let data = &self.input[self.pos..end];
+                self.pos = end;
+                Ok(data)
+            }
+            _ => Err(DecodeError::UnexpectedEnd { expected: EXPECTED }),
+        }
+    }
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+    fn skip_while<F: Fn(u8) -> bool>(&mut self, f: F) -> usize {
+        let start = self.pos;
+        while self.peek().is_some_and(&f) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+    fn skip_whitespace(&mut self) {
+        // IEEE 488.2: 7.4.1.2 - <white space>, excluding the newline terminator
+        self.skip_while(|b| b <= b' ' && b != b'\n');
+    }
+    fn expect(&mut self, expected_byte: u8, expected: &'static str) -> Result<(), DecodeError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected_byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(expected))
+        }
+    }
+    fn ascii(&self, start: usize) -> Result<&'a str, DecodeError> {
+        str::from_utf8(&self.input[start..self.pos]).map_err(|_| DecodeError::Syntax {
+            expected: "ASCII data",
+            position: start,
+        })
+    }
+    fn error(&self, expected: &'static str) -> DecodeError {
+        if self.pos < self.input.len() {
+            DecodeError::Syntax {
+                expected,
+                position: self.pos,
+            }
+        } else {
+            DecodeError::UnexpectedEnd { expected }
+        }
+    }
+}
+
+impl<'a> FromResponse<'a> for Discrete<'a> {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        p.character().map(Discrete)
+    }
+}
+
+#[test]
+fn test_discrete_response() {
+    assert_eq!(parse::<Discrete>(b"VOLT\n"), Ok(Discrete("VOLT")));
+    assert_eq!(parse::<Discrete>(b"CH_1"), Ok(Discrete("CH_1")));
+    assert_eq!(
+        parse::<Discrete>(b"1ST"),
+        Err(DecodeError::Syntax {
+            expected: "character response data",
+            position: 0
+        })
+    );
+    assert_eq!(
+        parse::<Discrete>(b"VOLT CURR"),
+        Err(DecodeError::TrailingData { position: 5 })
+    );
+}
+
+/// Decodes character response data that matches one of the given short/long forms
+fn keyword<'a, T: Copy>(
+    p: &mut Parser<'a>,
+    keywords: &[(&str, &str, T)],
+    expected: &'static str,
+) -> Result<T, DecodeError> {
+    let start = p.position();
+    let text = p.character().map_err(|_| p.error(expected))?;
+    keywords
+        .iter()
+        .find(|(short, long, _)| {
+            text.eq_ignore_ascii_case(short) || text.eq_ignore_ascii_case(long)
+        })
+        .map(|&(_, _, value)| value)
+        .ok_or(DecodeError::Syntax {
+            expected,
+            position: start,
+        })
+}
+
+impl<'a> FromResponse<'a> for DefaultValue {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        keyword(p, &[("DEF", "DEFAULT", DefaultValue)], "DEFault")
+    }
+}
+
+impl<'a> FromResponse<'a> for Limit {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        keyword(
+            p,
+            &[
+                ("MIN", "MINIMUM", Limit::Min),
+                ("MAX", "MAXIMUM", Limit::Max),
+            ],
+            "MINimum or MAXimum",
+        )
+    }
+}
+
+impl<'a> FromResponse<'a> for Step {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        keyword(
+            p,
+            &[("UP", "UP", Step::Up), ("DOWN", "DOWN", Step::Down)],
+            "UP or DOWN",
+        )
+    }
+}
+
+#[test]
+fn test_keyword_response() {
+    assert_eq!(parse::<DefaultValue>(b"DEF"), Ok(DefaultValue));
+    assert_eq!(parse::<Limit>(b"maximum"), Ok(Limit::Max));
+    assert_eq!(parse::<Limit>(b"MIN"), Ok(Limit::Min));
+    assert_eq!(parse::<Step>(b"DOWN"), Ok(Step::Down));
+    assert_eq!(
+        parse::<Limit>(b"MINI"),
+        Err(DecodeError::Syntax {
+            expected: "MINimum or MAXimum",
+            position: 0
+        })
+    );
+}
+
+impl<'a> FromResponse<'a> for String {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        p.string()
+    }
+}
+
+#[test]
+fn test_string_response() {
+    assert_eq!(parse::<String>(b"\"foo\"\n"), Ok("foo".to_owned()));
+    assert_eq!(
+        parse::<String>(br#""what if ""quotes"" break 'stuff'?""#),
+        Ok(r#"what if "quotes" break 'stuff'?"#.to_owned())
+    );
+    assert_eq!(parse::<String>(b"'it''s'"), Ok("it's".to_owned()));
+    assert_eq!(
+        parse::<String>(b"\"unterminated"),
+        Err(DecodeError::UnexpectedEnd {
+            expected: "string response data"
+        })
+    );
+}
+
+impl<'a> FromResponse<'a> for Block<'a> {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        p.block().map(Block)
+    }
+}
+
+#[test]
+fn test_block_response() {
+    assert_eq!(
+        parse::<Block>(b"#13\x11\x0a\x33\n"),
+        Ok(Block(&[0x11, 0x0a, 0x33]))
+    );
+    assert_eq!(
+        parse::<Block>(b"#0\x11\x0a\x33\n"),
+        Ok(Block(&[0x11, 0x0a, 0x33]))
+    );
+    assert_eq!(parse::<Block>(b"#10"), Ok(Block(&[])));
+    assert_eq!(
+        parse::<Block>(b"#15\x11\x22"),
+        Err(DecodeError::UnexpectedEnd {
+            expected: "arbitrary block response data"
+        })
+    );
+    assert_eq!(
+        parse::<Block>(b"#2A"),
+        Err(DecodeError::Syntax {
+            expected: "arbitrary block response data",
+            position: 2
+        })
+    );
+}
+
+const CHANNEL_LIST: &str = "channel list";
+
+fn channel<'a>(p: &mut Parser<'a>) -> Result<Channel, DecodeError> {
+    let mut dimensions = Vec::new();
+    loop {
+        p.skip_whitespace();
+        let start = p.position();
+        if p.skip_while(|b| b.is_ascii_digit()) == 0 {
+            return Err(p.error(CHANNEL_LIST));
+        }
+        let dimension = p
+            .ascii(start)?
+            .parse()
+            .map_err(|_| DecodeError::OutOfRange { position: start })?;
+        dimensions.push(dimension);
+        if p.peek() != Some(b'!') {
+            return Ok(Channel(dimensions));
+        }
+        p.pos += 1;
+    }
+}
+
+fn channel_entries<'a>(p: &mut Parser<'a>) -> Result<Vec<ChannelEntry>, DecodeError> {
+    let mut entries = Vec::new();
+    p.skip_whitespace();
+    if p.peek() == Some(b')') {
+        p.pos += 1;
+        return Ok(entries);
+    }
+    loop {
+        p.skip_whitespace();
+        if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            let module = p.character()?.to_owned();
+            p.expect(b'(', CHANNEL_LIST)?;
+            entries.push(ChannelEntry::Module(module, channel_entries(p)?));
+        } else {
+            let first = channel(p)?;
+            p.skip_whitespace();
+            if p.peek() == Some(b':') {
+                p.pos += 1;
+                entries.push(ChannelEntry::Range(first, channel(p)?));
+            } else {
+                entries.push(ChannelEntry::Channel(first));
+            }
+        }
+        p.skip_whitespace();
+        match p.peek() {
+            Some(b',') => p.pos += 1,
+            Some(b')') => {
+                p.pos += 1;
+                return Ok(entries);
+            }
+            _ => return Err(p.error(CHANNEL_LIST)),
+        }
+    }
+}
+
+impl<'a> FromResponse<'a> for ChannelList {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        // IEEE 488.2: 8.7.11 - <EXPRESSION RESPONSE DATA>
+        p.expect(b'(', CHANNEL_LIST)?;
+        if p.peek() != Some(b'@') {
+            return Err(p.error(CHANNEL_LIST));
+        }
+        p.pos += 1;
+        channel_entries(p).map(ChannelList)
+    }
+}
+
+#[test]
+fn test_channel_list_response() {
+    assert_eq!(
+        parse::<ChannelList>(b"(@1,3:5)\n"),
+        Ok(ChannelList::new().channel(1).range(3, 5))
+    );
+    assert_eq!(
+        parse::<ChannelList>(b"(@1!2,3!4:3!8)"),
+        Ok(ChannelList::new()
+            .channel(Channel(vec![1, 2]))
+            .range(Channel(vec![3, 4]), Channel(vec![3, 8])))
+    );
+    assert_eq!(
+        parse::<ChannelList>(b"(@CARD1(1,3:5),CARD2(7))"),
+        Ok(ChannelList::new()
+            .module("CARD1", ChannelList::new().channel(1).range(3, 5))
+            .module("CARD2", ChannelList::new().channel(7)))
+    );
+    assert_eq!(parse::<ChannelList>(b"(@)"), Ok(ChannelList::new()));
+    assert_eq!(
+        parse::<ChannelList>(b"(1,2)"),
+        Err(DecodeError::Syntax {
+            expected: "channel list",
+            position: 1
+        })
+    );
+    assert_eq!(
+        parse::<ChannelList>(b"(@1,3:"),
+        Err(DecodeError::UnexpectedEnd {
+            expected: "channel list"
+        })
+    );
+}
+
+impl<'a> FromResponse<'a> for bool {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        // SCPI 1999.0: 7.3 - Boolean Program Data
+        p.skip_whitespace();
+        if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+            keyword(p, &[("ON", "ON", true), ("OFF", "OFF", false)], "boolean")
+        } else {
+            f64::from_response(p).map(|value| value.round() != 0.0)
+        }
+    }
+}
+
+#[test]
+fn test_bool_response() {
+    assert_eq!(parse::<bool>(b"1\n"), Ok(true));
+    assert_eq!(parse::<bool>(b"0"), Ok(false));
+    assert_eq!(parse::<bool>(b"+0.00000E+00"), Ok(false));
+    assert_eq!(parse::<bool>(b"ON"), Ok(true));
+    assert_eq!(
+        parse::<bool>(b"YES"),
+        Err(DecodeError::Syntax {
+            expected: "boolean",
+            position: 0
+        })
+    );
+}
+
+macro_rules! impl_float_response {
+    ($($ty:ident),*) => {
+        $(
+            impl<'a> FromResponse<'a> for $ty {
+                fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+                    p.skip_whitespace();
+                    if p.peek().is_some_and(|b| b.is_ascii_alphabetic()) {
+                        return keyword(
+                            p,
+                            &[
+                                ("NAN", "NAN", $ty::NAN),
+                                ("INF", "INFINITY", $ty::INFINITY),
+                                ("NINF", "NINFINITY", $ty::NEG_INFINITY),
+                            ],
+                            "numeric response data",
+                        );
+                    }
+                    let start = p.position();
+                    let text = p.numeric()?;
+                    let wide: f64 = text
+                        .parse()
+                        .map_err(|_| DecodeError::OutOfRange { position: start })?;
+                    // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+                    // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
+                    if wide == 9.91E37 {
+                        Ok($ty::NAN)
+                    } else if wide == 9.9E37 {
+                        Ok($ty::INFINITY)
+                    } else if wide == -9.9E37 {
+                        Ok($ty::NEG_INFINITY)
+                    } else {
+                        match text.parse::<$ty>() {
+                            Ok(value) if value.is_finite() => Ok(value),
+                            _ => Err(DecodeError::OutOfRange { position: start }),
+                        }
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_float_response!(f32, f64);
+
+#[test]
+fn test_float_response() {
+    assert_eq!(parse::<f64>(b"1\n"), Ok(1.0));
+    assert_eq!(parse::<f64>(b"-12.5"), Ok(-12.5));
+    assert_eq!(parse::<f64>(b"+1.000000001E+09"), Ok(1.000000001E9));
+    assert_eq!(parse::<f32>(b".5e-3"), Ok(0.5E-3));
+    assert_eq!(parse::<f64>(b"9.9E37"), Ok(f64::INFINITY));
+    assert_eq!(parse::<f32>(b"-9.9E+37"), Ok(f32::NEG_INFINITY));
+    assert!(parse::<f64>(b"9.91E37").unwrap().is_nan());
+    assert!(parse::<f32>(b"NAN").unwrap().is_nan());
+    assert_eq!(
+        parse::<f32>(b"1E39"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<f64>(b"1.5E"),
+        Err(DecodeError::UnexpectedEnd {
+            expected: "numeric response data"
+        })
+    );
+    assert_eq!(
+        parse::<f64>(b"-"),
+        Err(DecodeError::Syntax {
+            expected: "numeric response data",
+            position: 0
+        })
+    );
+}
+
+macro_rules! impl_integer_response {
+    ($($ty:ident),*) => {
+        $(
+            impl<'a> FromResponse<'a> for $ty {
+                fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+                    p.skip_whitespace();
+                    let start = p.position();
+                    if p.peek() == Some(b'#') {
+                        let value = p.non_decimal()?;
+                        return $ty::try_from(value)
+                            .map_err(|_| DecodeError::OutOfRange { position: start });
+                    }
+                    let text = p.numeric()?;
+                    if let Ok(value) = text.parse::<$ty>() {
+                        return Ok(value);
+                    }
+                    let wide: f64 = text
+                        .parse()
+                        .map_err(|_| DecodeError::OutOfRange { position: start })?;
+                    // MAX rounds up when converted to f64, so the exclusive
+                    // power of two above it is used as the upper bound
+                    let limit = ($ty::MAX / 2 + 1) as f64 * 2.0;
+                    // Integral values sent as NR2/NR3 are accepted too
+                    if wide.fract() != 0.0 {
+                        Err(DecodeError::Syntax {
+                            expected: "integer response data",
+                            position: start,
+                        })
+                    } else if wide < $ty::MIN as f64 || wide >= limit {
+                        Err(DecodeError::OutOfRange { position: start })
+                    } else {
+                        Ok(wide as $ty)
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_response!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[test]
+fn test_integer_response() {
+    assert_eq!(parse::<i32>(b"-42\n"), Ok(-42));
+    assert_eq!(parse::<u8>(b"+255"), Ok(255));
+    assert_eq!(parse::<u16>(b"+1.28000000E+02"), Ok(128));
+    assert_eq!(
+        parse::<u8>(b"256"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<u8>(b"-1"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<u64>(b"18446744073709551616"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<i64>(b"9223372036854775808"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(parse::<i64>(b"-9223372036854775808"), Ok(i64::MIN));
+    assert_eq!(
+        parse::<i32>(b"1.5"),
+        Err(DecodeError::Syntax {
+            expected: "integer response data",
+            position: 0
+        })
+    );
+}
+
+macro_rules! impl_non_decimal_response {
+    ($($wrapper:ident),*) => {
+        $(
+            impl<'a, T: FromResponse<'a>> FromResponse<'a> for $wrapper<T> {
+                fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+                    p.parse().map($wrapper)
+                }
+            }
+        )*
+    };
+}
+
+impl_non_decimal_response!(Hex, Octal, Binary);
+
+#[test]
+fn test_non_decimal_response() {
+    assert_eq!(parse::<u8>(b"#H1F\n"), Ok(0x1f));
+    assert_eq!(parse::<u16>(b"#q777"), Ok(0o777));
+    assert_eq!(parse::<i32>(b"#B1010"), Ok(0b1010));
+    assert_eq!(parse::<Hex<u32>>(b"#HDEADBEEF"), Ok(Hex(0xdead_beef)));
+    assert_eq!(parse::<Binary<u8>>(b"42"), Ok(Binary(42)));
+    assert_eq!(parse::<Octal<u8>>(b"#Q17"), Ok(Octal(0o17)));
+    assert_eq!(
+        parse::<u8>(b"#H100"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<u64>(b"#H10000000000000000"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+    assert_eq!(
+        parse::<u8>(b"#B102"),
+        Err(DecodeError::TrailingData { position: 4 })
+    );
+    assert_eq!(
+        parse::<u8>(b"#X12"),
+        Err(DecodeError::Syntax {
+            expected: "non-decimal numeric response data",
+            position: 1
+        })
+    );
+}
+
+impl<'a, T: FromResponse<'a>> FromResponse<'a> for Vec<T> {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let mut result = Vec::new();
+        if p.at_unit_end() {
+            return Ok(result);
+        }
+        loop {
+            result.push(p.parse()?);
+            if p.at_unit_end() {
+                return Ok(result);
+            }
+            p.data_separator()?;
+        }
+    }
+}
+
+#[test]
+fn test_list_response() {
+    assert_eq!(parse::<Vec<i32>>(b"1,2,3\n"), Ok(vec![1, 2, 3]));
+    assert_eq!(parse::<Vec<f64>>(b"\n"), Ok(vec![]));
+    assert_eq!(
+        parse::<Vec<Discrete>>(b"CH1, CH2"),
+        Ok(vec![Discrete("CH1"), Discrete("CH2")])
+    );
+    assert_eq!(
+        parse::<Vec<i32>>(b"1,,3"),
+        Err(DecodeError::Syntax {
+            expected: "numeric response data",
+            position: 2
+        })
+    );
+}
+
+impl<'a, A, B> FromResponse<'a> for (A, B)
+where
+    A: FromResponse<'a>,
+    B: FromResponse<'a>,
+{
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        Ok((a, b))
+    }
+}
+
+#[test]
+fn test_tuple2_response() {
+    assert_eq!(
+        parse::<(String, Discrete)>(br#""mixed",BAG"#),
+        Ok(("mixed".to_owned(), Discrete("BAG")))
+    );
+    assert_eq!(
+        parse::<(i32, i32)>(b"1;2"),
+        Err(DecodeError::Syntax {
+            expected: "response data separator",
+            position: 1
+        })
+    );
+}
+
+impl<'a, A, B, C> FromResponse<'a> for (A, B, C)
+where
+    A: FromResponse<'a>,
+    B: FromResponse<'a>,
+    C: FromResponse<'a>,
+{
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        p.data_separator()?;
+        let c = p.parse()?;
+        Ok((a, b, c))
+    }
+}
+
+#[test]
+fn test_tuple3_response() {
+    assert_eq!(
+        parse::<(u8, i8, f32)>(b"1,-1,-4.2E5\n"),
+        Ok((1, -1, -420000.0))
+    );
+}
+
+impl<'a, A, B, C, D> FromResponse<'a> for (A, B, C, D)
+where
+    A: FromResponse<'a>,
+    B: FromResponse<'a>,
+    C: FromResponse<'a>,
+    D: FromResponse<'a>,
+{
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let a = p.parse()?;
+        p.data_separator()?;
+        let b = p.parse()?;
+        p.data_separator()?;
+        let c = p.parse()?;
+        p.data_separator()?;
+        let d = p.parse()?;
+        Ok((a, b, c, d))
+    }
+}
+
+#[test]
+fn test_message_units() {
+    let mut p = Parser::new(b"1.5;\"ok\";#12\x00\x01\n");
+    assert_eq!(p.parse::<f64>(), Ok(1.5));
+    p.unit_separator().unwrap();
+    assert_eq!(p.parse::<String>(), Ok("ok".to_owned()));
+    p.unit_separator().unwrap();
+    assert_eq!(p.parse::<Block>(), Ok(Block(&[0x00, 0x01])));
+    assert_eq!(p.finish(), Ok(()));
+}
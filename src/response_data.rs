@@ -0,0 +1,389 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{fmt, io::Write};
+
+use crate::{Binary, Block, Discrete, EncodeError, Hex, Octal};
+
+/// Trait for types that can be sent by instruments as SCPI response data
+///
+/// This is the instrument-side counterpart of `Parameter`. Program data is
+/// parsed leniently, but response data is always encoded using the single
+/// precise form defined for each data element.
+///
+/// Reference: IEEE 488.2: 6.4.4 - Precise Talking, 8.7 - Response Data
+pub trait Response {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError>;
+}
+
+/// Checks that the string only contains 7-bit ASCII characters
+fn check_ascii(s: &str) -> Result<(), EncodeError> {
+    match s.char_indices().find(|&(_, ch)| !ch.is_ascii()) {
+        Some((index, ch)) => Err(EncodeError::InvalidCharacter { ch, index }),
+        None => Ok(()),
+    }
+}
+
+impl<'a> Response for Discrete<'a> {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // IEEE 488.2: 8.7.1 - <CHARACTER RESPONSE DATA>
+        // The mnemonic is sent in upper case, so e.g. `PARallel` becomes `PARALLEL`
+        let mut bytes = self.0.bytes();
+        let is_mnemonic = bytes.next().is_some_and(|b| b.is_ascii_alphabetic())
+            && bytes.all(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if !is_mnemonic {
+            return Err(EncodeError::InvalidMnemonic(self.0.to_owned()));
+        }
+        w.write_all(self.0.to_ascii_uppercase().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_discrete_response() {
+    let mut buf = Vec::new();
+    Discrete("Volt_2").encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"VOLT_2");
+    for &invalid in &["", "1ST", "TWO WORDS"] {
+        let result = Discrete(invalid).encode_response(&mut buf);
+        assert!(matches!(result, Err(EncodeError::InvalidMnemonic(ref m)) if m == invalid));
+    }
+}
+
+impl Response for &str {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // IEEE 488.2: 8.7.8 - <STRING RESPONSE DATA>
+        check_ascii(self)?;
+        w.write_all(b"\"")?;
+        for ch in self.chars() {
+            match ch {
+                '"' => w.write_all(b"\"\"")?,
+                ch => w.write_all(&[ch as u8])?,
+            }
+        }
+        w.write_all(b"\"")?;
+        Ok(())
+    }
+}
+
+impl Response for String {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        self.as_str().encode_response(w)
+    }
+}
+
+#[test]
+fn test_str_response() {
+    let mut buf = Vec::new();
+    "say \"hi\"\n".encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"\"say \"\"hi\"\"\n\"");
+    let result = String::from("\u{b5}s").encode_response(&mut buf);
+    assert!(matches!(
+        result,
+        Err(EncodeError::InvalidCharacter {
+            ch: '\u{b5}',
+            index: 0
+        })
+    ));
+}
+
+impl<'a> Response for Block<'a> {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // IEEE 488.2: 8.7.9 - <DEFINITE LENGTH ARBITRARY BLOCK RESPONSE DATA>
+        let len = self.0.len().to_string();
+        // The number of length digits must fit in a single digit
+        if len.len() > 9 {
+            return Err(EncodeError::OutOfRange(self.0.len() as f64));
+        }
+        write!(w, "#{}{}", len.len(), len)?;
+        w.write_all(self.0)?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_block_response() {
+    let mut buf = Vec::new();
+    Block(&[0; 12]).encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"#212\0\0\0\0\0\0\0\0\0\0\0\0");
+    buf.clear();
+    Block(b"").encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"#10");
+}
+
+/// Encodes a floating point value as NR3 numeric response data (e.g. `1.5E-3`)
+///
+/// Special values are sent using the numbers reserved for them by SCPI.
+fn encode_nr3<T, W>(value: T, w: &mut W) -> Result<(), EncodeError>
+where
+    T: Copy + Into<f64> + fmt::UpperExp,
+    W: Write,
+{
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        // SCPI 1999.0: 7.2.1.5 - Not A Number (NAN)
+        w.write_all(b"9.91E+37")?;
+        return Ok(());
+    } else if wide.is_infinite() {
+        // SCPI 1999.0: 7.2.1.4 - INFinity and Negative INFinity (NINF)
+        if wide.is_sign_positive() {
+            w.write_all(b"9.9E+37")?;
+        } else {
+            w.write_all(b"-9.9E+37")?;
+        }
+        return Ok(());
+    } else if !(-9.9E37..=9.9E37).contains(&wide) {
+        return Err(EncodeError::OutOfRange(wide));
+    }
+    // IEEE 488.2: 8.7.4 - <NR3 NUMERIC RESPONSE DATA>
+    let text = format!("{:E}", value);
+    let (mantissa, exponent) = text.split_at(text.find('E').unwrap_or(text.len()));
+    w.write_all(mantissa.as_bytes())?;
+    if !mantissa.contains('.') {
+        w.write_all(b".0")?;
+    }
+    match exponent.strip_prefix("E-") {
+        Some(digits) => write!(w, "E-{}", digits)?,
+        None => write!(w, "E+{}", &exponent[1..])?,
+    }
+    Ok(())
+}
+
+impl Response for f32 {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_nr3(self, w)
+    }
+}
+
+impl Response for f64 {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_nr3(self, w)
+    }
+}
+
+#[test]
+fn test_f32_response() {
+    let mut buf = Vec::new();
+    (-4.2E5f32).encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"-4.2E+5");
+}
+
+#[test]
+fn test_f64_response() {
+    fn encode(value: f64) -> String {
+        let mut buf = Vec::new();
+        value.encode_response(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+    assert_eq!(encode(5.0), "5.0E+0");
+    assert_eq!(encode(1.5E-3), "1.5E-3");
+    assert_eq!(encode(f64::NAN), "9.91E+37");
+    assert_eq!(encode(f64::INFINITY), "9.9E+37");
+    assert_eq!(encode(f64::NEG_INFINITY), "-9.9E+37");
+    assert!(matches!(
+        1E38.encode_response(&mut Vec::new()),
+        Err(EncodeError::OutOfRange(_))
+    ));
+}
+
+macro_rules! impl_nr1_response {
+    ($($t:ty),*) => {
+        $(
+            impl Response for $t {
+                fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    // IEEE 488.2: 8.7.2 - <NR1 NUMERIC RESPONSE DATA>
+                    write!(w, "{}", self)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_nr1_response!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+#[test]
+fn test_nr1_response() {
+    let mut buf = Vec::new();
+    (-128i8, 0u8, u64::MAX).encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"-128,0,18446744073709551615");
+}
+
+macro_rules! impl_non_decimal_response {
+    ($($t:ty),*) => {
+        $(
+            // IEEE 488.2: 8.7.5..8.7.7 - <HEXADECIMAL/OCTAL/BINARY NUMERIC RESPONSE DATA>
+            impl Response for Hex<$t> {
+                fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#H{:X}", self.0)?;
+                    Ok(())
+                }
+            }
+            impl Response for Octal<$t> {
+                fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#Q{:o}", self.0)?;
+                    Ok(())
+                }
+            }
+            impl Response for Binary<$t> {
+                fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    write!(w, "#B{:b}", self.0)?;
+                    Ok(())
+                }
+            }
+        )*
+    };
+}
+
+impl_non_decimal_response!(u8, u16, u32, u64, usize);
+
+#[test]
+fn test_non_decimal_response() {
+    let mut buf = Vec::new();
+    (Hex(0xbeefu16), Octal(8u8), Binary(5u32))
+        .encode_response(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#HBEEF,#Q10,#B101");
+}
+
+impl Response for bool {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        // SCPI 1999.0: 7.3 - Boolean Program Data
+        w.write_all(match self {
+            true => b"1",
+            false => b"0",
+        })?;
+        Ok(())
+    }
+}
+
+#[test]
+fn test_bool_response() {
+    let mut buf = Vec::new();
+    (true, false).encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"1,0");
+}
+
+impl Response for () {
+    fn encode_response<W>(self, _w: &mut W) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+/// Encodes the items of a list separated by commas
+fn encode_list<I, W>(items: I, w: &mut W) -> Result<(), EncodeError>
+where
+    I: IntoIterator,
+    I::Item: Response,
+    W: Write,
+{
+    for (index, item) in items.into_iter().enumerate() {
+        if index > 0 {
+            w.write_all(b",")?;
+        }
+        item.encode_response(w)?;
+    }
+    Ok(())
+}
+
+impl<T: Response> Response for Vec<T> {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_list(self, w)
+    }
+}
+
+impl<T: Response + Copy> Response for &[T] {
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        encode_list(self.iter().copied(), w)
+    }
+}
+
+#[test]
+fn test_list_response() {
+    let mut buf = Vec::new();
+    vec![1.0f64, 2.5].encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"1.0E+0,2.5E+0");
+    buf.clear();
+    (&[3u8, 4][..]).encode_response(&mut buf).unwrap();
+    assert_eq!(buf, b"3,4");
+    buf.clear();
+    Vec::<u8>::new().encode_response(&mut buf).unwrap();
+    assert!(buf.is_empty());
+}
+
+impl<A, B> Response for (A, B)
+where
+    A: Response,
+    B: Response,
+{
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        self.0.encode_response(w)?;
+        w.write_all(b",")?;
+        self.1.encode_response(w)
+    }
+}
+
+#[test]
+fn test_response_tuple2() {
+    let mut buf = Vec::new();
+    (-113, "Undefined header")
+        .encode_response(&mut buf)
+        .unwrap();
+    assert_eq!(buf, br#"-113,"Undefined header""#);
+}
+
+impl<A, B, C> Response for (A, B, C)
+where
+    A: Response,
+    B: Response,
+    C: Response,
+{
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        self.0.encode_response(w)?;
+        w.write_all(b",")?;
+        self.1.encode_response(w)?;
+        w.write_all(b",")?;
+        self.2.encode_response(w)
+    }
+}
+
+impl<A, B, C, D> Response for (A, B, C, D)
+where
+    A: Response,
+    B: Response,
+    C: Response,
+    D: Response,
+{
+    fn encode_response<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        self.0.encode_response(w)?;
+        w.write_all(b",")?;
+        self.1.encode_response(w)?;
+        w.write_all(b",")?;
+        self.2.encode_response(w)?;
+        w.write_all(b",")?;
+        self.3.encode_response(w)
+    }
+}
+
+#[test]
+fn test_response_tuple4() {
+    let mut buf = Vec::new();
+    ("ACME", Discrete("psu"), 1.5f32, Block(b"\n"))
+        .encode_response(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"\"ACME\",PSU,1.5E+0,#11\n");
+}
+
+#[test]
+fn test_response_round_trip() {
+    let mut buf = Vec::new();
+    (1.25E-7f64, f64::NAN, -3i32, "a\"b")
+        .encode_response(&mut buf)
+        .unwrap();
+    let (value, nan, int, text): (f64, f64, i32, String) = crate::response::parse(&buf).unwrap();
+    assert_eq!(value, 1.25E-7);
+    assert!(nan.is_nan());
+    assert_eq!(int, -3);
+    assert_eq!(text, "a\"b");
+}
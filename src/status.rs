@@ -0,0 +1,242 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    io::Write,
+    ops::{BitAnd, BitOr, BitOrAssign},
+};
+
+use crate::{
+    response::{FromResponse, Parser},
+    DecodeError, EncodeError, Parameter,
+};
+
+pub use self::model::{StatusModel, StatusRegister};
+
+mod model;
+
+macro_rules! register {
+    (
+        $(#[$attr:meta])*
+        $ty:ident($bits:ident) {
+            $($(#[$flag_attr:meta])* $flag:ident = $bit:expr;)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[repr(transparent)]
+        #[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+        pub struct $ty(pub $bits);
+
+        impl $ty {
+            $(
+                $(#[$flag_attr])*
+                pub const $flag: $ty = $ty(1 << $bit);
+            )*
+            /// Returns a register value with no bits set
+            pub fn empty() -> $ty {
+                $ty(0)
+            }
+            /// Returns the raw register value
+            pub fn bits(self) -> $bits {
+                self.0
+            }
+            /// Returns true if no bits are set
+            pub fn is_empty(self) -> bool {
+                self.0 == 0
+            }
+            /// Returns true if all bits of `other` are set
+            pub fn contains(self, other: $ty) -> bool {
+                self.0 & other.0 == other.0
+            }
+            /// Returns true if any bit of `other` is set
+            pub fn intersects(self, other: $ty) -> bool {
+                self.0 & other.0 != 0
+            }
+            /// Sets all bits of `other`
+            pub fn insert(&mut self, other: $ty) {
+                self.0 |= other.0;
+            }
+            /// Clears all bits of `other`
+            pub fn remove(&mut self, other: $ty) {
+                self.0 &= !other.0;
+            }
+        }
+
+        impl BitOr for $ty {
+            type Output = $ty;
+            fn bitor(self, rhs: $ty) -> $ty {
+                $ty(self.0 | rhs.0)
+            }
+        }
+
+        impl BitOrAssign for $ty {
+            fn bitor_assign(&mut self, rhs: $ty) {
+                self.0 |= rhs.0;
+            }
+        }
+
+        impl BitAnd for $ty {
+            type Output = $ty;
+            fn bitand(self, rhs: $ty) -> $ty {
+                $ty(self.0 & rhs.0)
+            }
+        }
+
+        impl From<$bits> for $ty {
+            fn from(bits: $bits) -> $ty {
+                $ty(bits)
+            }
+        }
+
+        impl From<$ty> for $bits {
+            fn from(register: $ty) -> $bits {
+                register.0
+            }
+        }
+
+        impl Parameter for $ty {
+            fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                write!(w, "{}", self.0)?;
+                Ok(())
+            }
+        }
+
+        impl<'a> FromResponse<'a> for $ty {
+            fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+                p.parse().map($ty)
+            }
+        }
+    };
+}
+
+register! {
+    /// Status Byte Register
+    ///
+    /// Reference: IEEE 488.2: 11.2 - Status Byte Register, SCPI 1999.0: 9.1 - Status Reporting
+    StatusByte(u8) {
+        /// Error/event queue is not empty
+        EAV = 2;
+        /// Questionable status summary
+        QUES = 3;
+        /// Message available in the output queue
+        MAV = 4;
+        /// Event status bit, summary of the standard event status register
+        ESB = 5;
+        /// Request service (serial poll) / master summary status (`*STB?`)
+        RQS = 6;
+        /// Operation status summary
+        OPER = 7;
+    }
+}
+
+impl StatusByte {
+    /// Master summary status, which shares the bit with `RQS`
+    pub const MSS: StatusByte = StatusByte::RQS;
+}
+
+register! {
+    /// Standard Event Status Register
+    ///
+    /// Reference: IEEE 488.2: 11.5.1 - Standard Event Status Register
+    StandardEventStatus(u8) {
+        /// Operation complete
+        OPC = 0;
+        /// Request control
+        RQC = 1;
+        /// Query error
+        QYE = 2;
+        /// Device dependent error
+        DDE = 3;
+        /// Execution error
+        EXE = 4;
+        /// Command error
+        CME = 5;
+        /// User request
+        URQ = 6;
+        /// Power on
+        PON = 7;
+    }
+}
+
+register! {
+    /// Operation Status Register
+    ///
+    /// Reference: SCPI 1999.0: 9.4 - STATus:OPERation
+    OperationStatus(u16) {
+        CALIBRATING = 0;
+        SETTLING = 1;
+        RANGING = 2;
+        SWEEPING = 3;
+        MEASURING = 4;
+        WAITING_FOR_TRIGGER = 5;
+        WAITING_FOR_ARM = 6;
+        CORRECTING = 7;
+        /// Summary of instrument operation registers
+        INSTRUMENT_SUMMARY = 13;
+        PROGRAM_RUNNING = 14;
+    }
+}
+
+register! {
+    /// Questionable Status Register
+    ///
+    /// Reference: SCPI 1999.0: 9.5 - STATus:QUEStionable
+    QuestionableStatus(u16) {
+        VOLTAGE = 0;
+        CURRENT = 1;
+        TIME = 2;
+        POWER = 3;
+        TEMPERATURE = 4;
+        FREQUENCY = 5;
+        PHASE = 6;
+        MODULATION = 7;
+        CALIBRATION = 8;
+        /// Summary of instrument questionable registers
+        INSTRUMENT_SUMMARY = 13;
+        COMMAND_WARNING = 14;
+    }
+}
+
+#[test]
+fn test_status_byte() {
+    let stb = StatusByte(0x70);
+    assert!(stb.contains(StatusByte::MAV | StatusByte::ESB));
+    assert!(stb.contains(StatusByte::MSS));
+    assert!(!stb.intersects(StatusByte::OPER | StatusByte::QUES));
+    let mut stb = StatusByte::empty();
+    stb |= StatusByte::EAV;
+    stb.insert(StatusByte::OPER);
+    assert_eq!(stb.bits(), 0x84);
+    stb.remove(StatusByte::EAV);
+    assert_eq!(stb, StatusByte::OPER);
+}
+
+#[test]
+fn test_register_parameter() {
+    let mut buf = Vec::new();
+    (
+        StandardEventStatus::CME | StandardEventStatus::EXE,
+        QuestionableStatus::COMMAND_WARNING,
+    )
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"48,16384");
+}
+
+#[test]
+fn test_register_response() {
+    use crate::response::parse;
+    assert_eq!(
+        parse::<StandardEventStatus>(b"+129\n"),
+        Ok(StandardEventStatus::PON | StandardEventStatus::OPC)
+    );
+    assert_eq!(
+        parse::<OperationStatus>(b"16\n"),
+        Ok(OperationStatus::MEASURING)
+    );
+    assert_eq!(
+        parse::<StatusByte>(b"256\n"),
+        Err(DecodeError::OutOfRange { position: 0 })
+    );
+}
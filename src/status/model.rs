@@ -0,0 +1,425 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use crate::{
+    dispatch::{Call, CommandTree},
+    error_queue::{ErrorQueue, ScpiError},
+    status::{OperationStatus, QuestionableStatus, StandardEventStatus, StatusByte},
+};
+
+/// Value of a transition filter that passes all defined bits
+const ALL_BITS: u16 = 0x7fff;
+
+/// SCPI status register structure with condition, transition filter, event and enable registers
+///
+/// Reference: SCPI 1999.0: 9.3 - Status Register Structure
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct StatusRegister<T> {
+    condition: T,
+    ptr: T,
+    ntr: T,
+    event: T,
+    enable: T,
+}
+
+impl<T> Default for StatusRegister<T>
+where
+    T: Copy + Default + Eq + From<u16> + Into<u16>,
+{
+    fn default() -> Self {
+        StatusRegister::new()
+    }
+}
+
+impl<T> StatusRegister<T>
+where
+    T: Copy + Default + Eq + From<u16> + Into<u16>,
+{
+    /// Returns a register structure in its preset state
+    pub fn new() -> StatusRegister<T> {
+        StatusRegister {
+            condition: T::default(),
+            ptr: T::from(ALL_BITS),
+            ntr: T::default(),
+            event: T::default(),
+            enable: T::default(),
+        }
+    }
+    pub fn condition(&self) -> T {
+        self.condition
+    }
+    /// Updates the condition register, latching transitions that pass the filters into the event register
+    pub fn set_condition(&mut self, condition: T) {
+        let old = self.condition.into();
+        let new = condition.into();
+        let rising = new & !old & self.ptr.into();
+        let falling = old & !new & self.ntr.into();
+        self.event = T::from(self.event.into() | rising | falling);
+        self.condition = condition;
+    }
+    /// Sets the condition bits of `bits`
+    pub fn insert_condition(&mut self, bits: T) {
+        self.set_condition(T::from(self.condition.into() | bits.into()));
+    }
+    /// Clears the condition bits of `bits`
+    pub fn remove_condition(&mut self, bits: T) {
+        self.set_condition(T::from(self.condition.into() & !bits.into()));
+    }
+    /// Returns the event register without clearing it
+    pub fn event(&self) -> T {
+        self.event
+    }
+    /// Returns and clears the event register, like reading it with `[:EVENt]?`
+    pub fn take_event(&mut self) -> T {
+        std::mem::take(&mut self.event)
+    }
+    pub fn enable(&self) -> T {
+        self.enable
+    }
+    pub fn set_enable(&mut self, enable: T) {
+        self.enable = enable;
+    }
+    /// Returns the positive transition filter
+    pub fn ptr(&self) -> T {
+        self.ptr
+    }
+    pub fn set_ptr(&mut self, ptr: T) {
+        self.ptr = ptr;
+    }
+    /// Returns the negative transition filter
+    pub fn ntr(&self) -> T {
+        self.ntr
+    }
+    pub fn set_ntr(&mut self, ntr: T) {
+        self.ntr = ntr;
+    }
+    /// Returns true if any enabled event is set, which sets the summary bit of the register
+    pub fn summary(&self) -> bool {
+        self.event.into() & self.enable.into() != 0
+    }
+    /// Clears the event register (`*CLS`)
+    pub fn clear(&mut self) {
+        self.event = T::default();
+    }
+    /// Resets the enable register and transition filters (`STATus:PRESet`)
+    ///
+    /// Reference: SCPI 1999.0: 20.7 - STATus:PRESet
+    pub fn preset(&mut self) {
+        self.enable = T::default();
+        self.ptr = T::from(ALL_BITS);
+        self.ntr = T::default();
+    }
+}
+
+#[test]
+fn test_status_register_transitions() {
+    let mut register = StatusRegister::<OperationStatus>::new();
+    register.insert_condition(OperationStatus::MEASURING);
+    assert_eq!(register.condition(), OperationStatus::MEASURING);
+    assert_eq!(register.event(), OperationStatus::MEASURING);
+
+    // Negative transitions are filtered out by default
+    register.clear();
+    register.remove_condition(OperationStatus::MEASURING);
+    assert!(register.event().is_empty());
+
+    register.set_ptr(OperationStatus::empty());
+    register.set_ntr(OperationStatus::SETTLING);
+    register.set_condition(OperationStatus::SETTLING | OperationStatus::SWEEPING);
+    assert!(register.event().is_empty());
+    register.set_condition(OperationStatus::empty());
+    assert_eq!(register.take_event(), OperationStatus::SETTLING);
+    assert!(register.event().is_empty());
+
+    register.preset();
+    assert_eq!(register.ptr(), OperationStatus(0x7fff));
+    assert!(register.ntr().is_empty());
+}
+
+#[test]
+fn test_status_register_summary() {
+    let mut register = StatusRegister::<QuestionableStatus>::new();
+    register.insert_condition(QuestionableStatus::VOLTAGE);
+    assert!(!register.summary());
+    register.set_enable(QuestionableStatus::VOLTAGE | QuestionableStatus::CURRENT);
+    assert!(register.summary());
+    register.remove_condition(QuestionableStatus::VOLTAGE);
+    assert!(register.summary());
+    register.take_event();
+    assert!(!register.summary());
+}
+
+/// IEEE 488.2 status reporting model of an instrument
+///
+/// Combines the standard event status register, the service request enable
+/// register and the SCPI OPERation and QUEStionable register structures into
+/// the status byte. The error/event queue is kept by the command tree, so
+/// methods that depend on it take it as a parameter.
+///
+/// Reference: IEEE 488.2: 11 - Device Status Reporting, SCPI 1999.0: 9 - Status Reporting
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StatusModel {
+    pub operation: StatusRegister<OperationStatus>,
+    pub questionable: StatusRegister<QuestionableStatus>,
+    esr: StandardEventStatus,
+    ese: StandardEventStatus,
+    sre: StatusByte,
+    message_available: bool,
+}
+
+impl StatusModel {
+    pub fn new() -> StatusModel {
+        StatusModel::default()
+    }
+    /// Returns a model in its power-on state, with the power on event set
+    pub fn power_on() -> StatusModel {
+        StatusModel {
+            esr: StandardEventStatus::PON,
+            ..StatusModel::default()
+        }
+    }
+    /// Sets events in the standard event status register (e.g. `OPC` or `URQ`)
+    pub fn set_event(&mut self, events: StandardEventStatus) {
+        self.esr |= events;
+    }
+    /// Returns and clears the standard event status register (`*ESR?`)
+    pub fn take_event_status(&mut self, errors: &mut ErrorQueue) -> StandardEventStatus {
+        self.esr |= errors.take_events();
+        std::mem::take(&mut self.esr)
+    }
+    /// Returns the standard event status enable register (`*ESE?`)
+    pub fn ese(&self) -> StandardEventStatus {
+        self.ese
+    }
+    pub fn set_ese(&mut self, ese: StandardEventStatus) {
+        self.ese = ese;
+    }
+    /// Returns the service request enable register (`*SRE?`)
+    pub fn sre(&self) -> StatusByte {
+        self.sre
+    }
+    /// Sets the service request enable register, ignoring the `RQS` bit
+    pub fn set_sre(&mut self, mut sre: StatusByte) {
+        sre.remove(StatusByte::RQS);
+        self.sre = sre;
+    }
+    /// Sets or clears the message available bit of the status byte
+    pub fn set_message_available(&mut self, available: bool) {
+        self.message_available = available;
+    }
+    /// Returns the status byte with the master summary status bit (`*STB?`)
+    ///
+    /// Reference: IEEE 488.2: 11.2.2.2 - Reading with the *STB? Query
+    pub fn status_byte(&mut self, errors: &mut ErrorQueue) -> StatusByte {
+        self.esr |= errors.take_events();
+        let mut stb = StatusByte::empty();
+        let summaries = [
+            (StatusByte::EAV, !errors.is_empty()),
+            (StatusByte::QUES, self.questionable.summary()),
+            (StatusByte::MAV, self.message_available),
+            (StatusByte::ESB, self.esr.intersects(self.ese)),
+            (StatusByte::OPER, self.operation.summary()),
+        ];
+        for &(bit, set) in &summaries {
+            if set {
+                stb.insert(bit);
+            }
+        }
+        if stb.intersects(self.sre) {
+            stb.insert(StatusByte::MSS);
+        }
+        stb
+    }
+    /// Clears all event registers and the error/event queue (`*CLS`)
+    ///
+    /// Enable registers, transition filters and the output queue are not affected.
+    ///
+    /// Reference: IEEE 488.2: 10.3 - *CLS, Clear Status Command
+    pub fn clear(&mut self, errors: &mut ErrorQueue) {
+        errors.clear();
+        errors.take_events();
+        self.esr = StandardEventStatus::empty();
+        self.operation.clear();
+        self.questionable.clear();
+    }
+    /// Presets the SCPI register structures (`STATus:PRESet`)
+    pub fn preset(&mut self) {
+        self.operation.preset();
+        self.questionable.preset();
+    }
+    /// Adds the status reporting commands and queries to a command tree
+    ///
+    /// Adds the common commands `*CLS`, `*ESE`, `*ESR?`, `*SRE`, `*STB?`,
+    /// `*OPC` and `*WAI`, and the `STATus` subsystem with the `OPERation` and
+    /// `QUEStionable` registers and `PRESet`. All commands complete
+    /// immediately, so `*OPC` sets the operation complete event right away.
+    pub fn commands<C: 'static>(
+        tree: CommandTree<C>,
+        model: fn(&mut C) -> &mut StatusModel,
+    ) -> CommandTree<C> {
+        let tree = tree
+            .command("*CLS", move |context, call| {
+                model(context).clear(call.errors());
+                Ok(())
+            })
+            .command_with_params("*ESE", move |context, call| {
+                let ese = parse_register(call, 0xff)?;
+                model(context).set_ese(StandardEventStatus(ese as u8));
+                Ok(())
+            })
+            .command("*ESE?", move |context, call| {
+                call.respond(model(context).ese().bits())
+            })
+            .command("*ESR?", move |context, call| {
+                let esr = model(context).take_event_status(call.errors());
+                call.respond(esr.bits())
+            })
+            .command_with_params("*SRE", move |context, call| {
+                let sre = parse_register(call, 0xff)?;
+                model(context).set_sre(StatusByte(sre as u8));
+                Ok(())
+            })
+            .command("*SRE?", move |context, call| {
+                call.respond(model(context).sre().bits())
+            })
+            .command("*STB?", move |context, call| {
+                let stb = model(context).status_byte(call.errors());
+                call.respond(stb.bits())
+            })
+            .command("*OPC", move |context, _| {
+                model(context).set_event(StandardEventStatus::OPC);
+                Ok(())
+            })
+            .command("*OPC?", |_, call| call.respond(true))
+            .command("*WAI", |_, _| Ok(()))
+            .command("STATus:PRESet", move |context, _| {
+                model(context).preset();
+                Ok(())
+            });
+        let tree = register_commands(tree, "STATus:OPERation", model, |model| {
+            &mut model.operation
+        });
+        register_commands(tree, "STATus:QUEStionable", model, |model| {
+            &mut model.questionable
+        })
+    }
+}
+
+/// Parses a register value from the program data of a command
+fn parse_register(call: &mut Call, max: u16) -> Result<u16, ScpiError> {
+    let value = call.parse::<i32>()?;
+    if value < 0 || value > i32::from(max) {
+        return Err(ScpiError::new(-222, "Data out of range"));
+    }
+    Ok(value as u16)
+}
+
+/// Adds the commands and queries of a SCPI register structure to a command tree
+///
+/// Reference: SCPI 1999.0: 20 - STATus Subsystem
+fn register_commands<C: 'static, T>(
+    tree: CommandTree<C>,
+    node: &str,
+    model: fn(&mut C) -> &mut StatusModel,
+    select: fn(&mut StatusModel) -> &mut StatusRegister<T>,
+) -> CommandTree<C>
+where
+    T: Copy + Default + Eq + From<u16> + Into<u16> + 'static,
+{
+    tree.command(&format!("{}[:EVENt]?", node), move |context, call| {
+        let event = select(model(context)).take_event();
+        call.respond(event.into())
+    })
+    .command(&format!("{}:CONDition?", node), move |context, call| {
+        call.respond(select(model(context)).condition().into())
+    })
+    .command_with_params(&format!("{}:ENABle", node), move |context, call| {
+        let enable = parse_register(call, ALL_BITS)?;
+        select(model(context)).set_enable(T::from(enable));
+        Ok(())
+    })
+    .command(&format!("{}:ENABle?", node), move |context, call| {
+        call.respond(select(model(context)).enable().into())
+    })
+    .command_with_params(&format!("{}:PTRansition", node), move |context, call| {
+        let ptr = parse_register(call, ALL_BITS)?;
+        select(model(context)).set_ptr(T::from(ptr));
+        Ok(())
+    })
+    .command(&format!("{}:PTRansition?", node), move |context, call| {
+        call.respond(select(model(context)).ptr().into())
+    })
+    .command_with_params(&format!("{}:NTRansition", node), move |context, call| {
+        let ntr = parse_register(call, ALL_BITS)?;
+        select(model(context)).set_ntr(T::from(ntr));
+        Ok(())
+    })
+    .command(&format!("{}:NTRansition?", node), move |context, call| {
+        call.respond(select(model(context)).ntr().into())
+    })
+}
+
+#[cfg(test)]
+fn simulator() -> (StatusModel, CommandTree<StatusModel>) {
+    (
+        StatusModel::power_on(),
+        StatusModel::commands(CommandTree::new(), |model| model),
+    )
+}
+
+#[test]
+fn test_status_model_common_commands() {
+    let (mut model, mut tree) = simulator();
+    assert_eq!(tree.execute(&mut model, b"*ESR?;*ESR?\n"), b"128;0\n");
+    assert_eq!(tree.execute(&mut model, b"*ESE 60;*SRE 255\n"), b"");
+    assert_eq!(tree.execute(&mut model, b"*ESE?;*SRE?\n"), b"60;191\n");
+
+    // The command error sets CME and EAV, and ESB and MSS through the enable registers
+    tree.execute(&mut model, b"*BOGUS\n");
+    assert_eq!(tree.execute(&mut model, b"*STB?\n"), b"100\n");
+    assert_eq!(tree.execute(&mut model, b"*ESR?;*STB?\n"), b"32;68\n");
+    tree.execute(&mut model, b"*CLS\n");
+    assert_eq!(tree.execute(&mut model, b"*STB?\n"), b"0\n");
+
+    tree.execute(&mut model, b"*OPC\n");
+    assert_eq!(tree.execute(&mut model, b"*ESR?;*OPC?\n"), b"1;1\n");
+
+    tree.execute(&mut model, b"*ESE 256\n");
+    assert_eq!(
+        tree.execute(&mut model, b"SYST:ERR?\n"),
+        b"-222,\"Data out of range\"\n"
+    );
+    assert_eq!(model.ese().bits(), 60);
+}
+
+#[test]
+fn test_status_model_scpi_registers() {
+    let (mut model, mut tree) = simulator();
+    tree.execute(
+        &mut model,
+        b"STAT:OPER:ENAB 16;PTR 0;NTR 16;:STAT:QUES:ENAB 1;*SRE 136\n",
+    );
+    model.operation.insert_condition(OperationStatus::MEASURING);
+    model
+        .questionable
+        .insert_condition(QuestionableStatus::VOLTAGE);
+    assert_eq!(
+        tree.execute(
+            &mut model,
+            b"*STB?;STAT:OPER:COND?;EVEN?;:STAT:QUES?;*STB?\n"
+        ),
+        b"72;16;0;1;0\n"
+    );
+    model.operation.remove_condition(OperationStatus::MEASURING);
+    assert_eq!(tree.execute(&mut model, b"*STB?\n"), b"192\n");
+    assert_eq!(tree.execute(&mut model, b"STAT:OPER?;*STB?\n"), b"16;0\n");
+
+    tree.execute(&mut model, b"STAT:PRES\n");
+    assert_eq!(
+        tree.execute(&mut model, b"STAT:OPER:ENAB?;PTR?;NTR?;*SRE?\n"),
+        b"0;32767;0;136\n"
+    );
+    tree.execute(&mut model, b"STAT:QUES:ENAB 32768\n");
+    assert_eq!(tree.execute(&mut model, b"SYST:ERR:COUN?\n"), b"1\n");
+}
@@ -0,0 +1,361 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io::{self, BufRead};
+
+use crate::{response, Command, ProgramMessage, Query, Terminator, TransportError};
+
+pub use self::hislip::{HislipTransport, LockResult};
+pub use self::resource::{open, ParseResourceError, ResourceName};
+pub use self::serial::SerialTransport;
+pub use self::tcp::TcpTransport;
+pub use self::usbtmc::{DevDepMsgIn, UsbtmcDevice, UsbtmcFramer, UsbtmcTransport};
+pub use self::vxi11::{Vxi11Error, Vxi11Transport};
+
+mod hislip;
+mod resource;
+mod serial;
+mod tcp;
+mod usbtmc;
+mod vxi11;
+
+/// Connection to an instrument that exchanges complete messages
+pub trait Transport {
+    /// Sends a complete program message, including its terminator
+    ///
+    /// Transports that support the END message send it with the last byte.
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()>;
+    /// Receives a complete response message, including its terminator
+    fn read_message(&mut self) -> io::Result<Vec<u8>>;
+    /// Returns the program message terminator expected by the transport
+    fn terminator(&self) -> Terminator {
+        Terminator::Newline
+    }
+    /// Returns a program message writer configured for the transport
+    fn message(&self) -> ProgramMessage<Vec<u8>> {
+        ProgramMessage::new(Vec::new()).terminator(self.terminator())
+    }
+    /// Sends a single command
+    fn send<C: Command>(&mut self, command: C) -> Result<(), TransportError>
+    where
+        Self: Sized,
+    {
+        let mut msg = self.message();
+        msg.command(command)?;
+        let message = msg.finish()?;
+        self.write_message(&message)?;
+        Ok(())
+    }
+    /// Sends a single query and decodes its response
+    fn query<Q: Query>(&mut self, query: Q) -> Result<Q::Response, TransportError>
+    where
+        Self: Sized,
+    {
+        self.send(query)?;
+        let response = self.read_message()?;
+        Ok(response::parse(&response)?)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        (**self).write_message(message)
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        (**self).read_message()
+    }
+    fn terminator(&self) -> Terminator {
+        (**self).terminator()
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        (**self).write_message(message)
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        (**self).read_message()
+    }
+    fn terminator(&self) -> Terminator {
+        (**self).terminator()
+    }
+}
+
+/// Incremental scanner that finds the end of a newline terminated message
+///
+/// Newlines inside string data and definite length arbitrary blocks don't
+/// terminate the message. A `#` only starts a block at the beginning of a
+/// data element, so it can appear inside arbitrary ASCII response data
+/// (e.g. `FW#2.1`).
+///
+/// Reference: IEEE 488.2: 8.5 - <RESPONSE MESSAGE TERMINATOR>
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct TerminatorScanner {
+    state: ScanState,
+    single_quotes: bool,
+}
+
+#[derive(Copy, Clone, Debug)]
+enum ScanState {
+    Data {
+        element_start: bool,
+    },
+    Quoted(u8),
+    BlockStart,
+    BlockLength {
+        digits: u8,
+        len: usize,
+    },
+    Block(usize),
+    /// Malformed message that is skipped until its terminator
+    Invalid,
+}
+
+impl Default for ScanState {
+    fn default() -> Self {
+        ScanState::Data {
+            element_start: true,
+        }
+    }
+}
+
+impl TerminatorScanner {
+    /// Returns a scanner for program messages, which also accept single-quoted strings
+    ///
+    /// Response messages only use double quotes, so an apostrophe in arbitrary
+    /// ASCII response data must not start a string.
+    ///
+    /// Reference: IEEE 488.2: 7.7.5 - <STRING PROGRAM DATA>, 8.7.8 - <STRING RESPONSE DATA>
+    pub(crate) fn program() -> TerminatorScanner {
+        TerminatorScanner {
+            single_quotes: true,
+            ..TerminatorScanner::default()
+        }
+    }
+    /// Processes the next byte, returning true if it terminates the message
+    ///
+    /// A malformed message is skipped until its terminator, which returns an
+    /// `InvalidData` error, so the next message can still be read.
+    pub(crate) fn push(&mut self, b: u8) -> io::Result<bool> {
+        self.state = match self.state {
+            ScanState::Data { .. } | ScanState::BlockStart if b == b'\n' => {
+                self.state = ScanState::default();
+                return Ok(true);
+            }
+            ScanState::Data { element_start } => match b {
+                b'"' => ScanState::Quoted(b),
+                b'\'' if self.single_quotes => ScanState::Quoted(b),
+                b'#' if element_start => ScanState::BlockStart,
+                b',' | b';' => ScanState::Data {
+                    element_start: true,
+                },
+                b if b.is_ascii_whitespace() => ScanState::Data {
+                    element_start: true,
+                },
+                _ => ScanState::Data {
+                    element_start: false,
+                },
+            },
+            // Escaped quotes are handled as two consecutive strings
+            ScanState::Quoted(quote) if b == quote => ScanState::Data {
+                element_start: false,
+            },
+            ScanState::Quoted(quote) => ScanState::Quoted(quote),
+            ScanState::BlockStart => match b {
+                b'1'..=b'9' => ScanState::BlockLength {
+                    digits: b - b'0',
+                    len: 0,
+                },
+                _ => ScanState::Data {
+                    element_start: false,
+                },
+            },
+            ScanState::BlockLength { digits, len } => {
+                let len = match b {
+                    b'0'..=b'9' => len
+                        .checked_mul(10)
+                        .and_then(|len| len.checked_add(usize::from(b - b'0'))),
+                    _ => None,
+                };
+                match (digits - 1, len) {
+                    (_, None) => ScanState::Invalid,
+                    (0, Some(0)) => ScanState::Data {
+                        element_start: false,
+                    },
+                    (0, Some(len)) => ScanState::Block(len),
+                    (digits, Some(len)) => ScanState::BlockLength { digits, len },
+                }
+            }
+            ScanState::Block(1) => ScanState::Data {
+                element_start: false,
+            },
+            ScanState::Block(remaining) => ScanState::Block(remaining - 1),
+            ScanState::Invalid if b == b'\n' => {
+                self.state = ScanState::default();
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "invalid block length",
+                ));
+            }
+            ScanState::Invalid => ScanState::Invalid,
+        };
+        Ok(false)
+    }
+}
+
+/// Reads a newline terminated response message from a byte stream
+///
+/// Newlines inside string data and definite length arbitrary blocks don't
+/// terminate the message. A malformed message is consumed up to its
+/// terminator before an `InvalidData` error is returned. Bytes of a message
+/// that was interrupted by an I/O error are lost, so transports that can time
+/// out keep a `MessageReader` instead.
+///
+/// Reference: IEEE 488.2: 8.5 - <RESPONSE MESSAGE TERMINATOR>
+pub fn read_terminated<R: BufRead>(r: &mut R) -> io::Result<Vec<u8>> {
+    MessageReader::default().read(r)
+}
+
+/// Reads a newline terminated program message from a byte stream
+///
+/// Like `read_terminated`, but also skips newlines inside single-quoted strings.
+pub(crate) fn read_program_message<R: BufRead>(r: &mut R) -> io::Result<Vec<u8>> {
+    MessageReader::program().read(r)
+}
+
+/// Newline terminated message reader that keeps a partially read message
+///
+/// If reading fails with an I/O error (e.g. a timeout), the bytes read so
+/// far are kept and the next read continues the same message instead of
+/// starting in the middle of it.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MessageReader {
+    message: Vec<u8>,
+    scanner: TerminatorScanner,
+}
+
+impl MessageReader {
+    /// Returns a reader for program messages
+    pub(crate) fn program() -> MessageReader {
+        MessageReader {
+            message: Vec::new(),
+            scanner: TerminatorScanner::program(),
+        }
+    }
+    /// Reads the rest of the current message
+    pub(crate) fn read<R: BufRead>(&mut self, r: &mut R) -> io::Result<Vec<u8>> {
+        loop {
+            let buf = r.fill_buf()?;
+            if buf.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let (used, result) = self.scan(buf);
+            r.consume(used);
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+    /// Scans buffered bytes, returning how many were used and the result if the message ended
+    ///
+    /// The used bytes must be consumed from the buffer even if the result is an error.
+    pub(crate) fn scan(&mut self, buf: &[u8]) -> (usize, Option<io::Result<Vec<u8>>>) {
+        for (i, &b) in buf.iter().enumerate() {
+            match self.scanner.push(b) {
+                Ok(false) => (),
+                Ok(true) => {
+                    self.message.extend_from_slice(&buf[..=i]);
+                    return (i + 1, Some(Ok(std::mem::take(&mut self.message))));
+                }
+                Err(err) => {
+                    self.message.clear();
+                    return (i + 1, Some(Err(err)));
+                }
+            }
+        }
+        self.message.extend_from_slice(buf);
+        (buf.len(), None)
+    }
+}
+
+#[test]
+fn test_read_terminated() {
+    let mut input = &b"1.5,\"a\nb\",#14\n\n\n\n;#H1F\nNEXT"[..];
+    assert_eq!(
+        read_terminated(&mut input).unwrap(),
+        b"1.5,\"a\nb\",#14\n\n\n\n;#H1F\n"
+    );
+    assert_eq!(input, b"NEXT");
+    assert_eq!(
+        read_terminated(&mut input).unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn test_read_terminated_arbitrary_ascii() {
+    let mut input = &b"O'Brien Instruments,X1,1234,FW#2.1\nNEXT"[..];
+    assert_eq!(
+        read_terminated(&mut input).unwrap(),
+        b"O'Brien Instruments,X1,1234,FW#2.1\n"
+    );
+    assert_eq!(input, b"NEXT");
+}
+
+#[test]
+fn test_read_program_message() {
+    let mut input = &b"DISP:TEXT 'a\nb';:DATA #13\n\n\n\nNEXT"[..];
+    assert_eq!(
+        read_program_message(&mut input).unwrap(),
+        b"DISP:TEXT 'a\nb';:DATA #13\n\n\n\n"
+    );
+    assert_eq!(input, b"NEXT");
+}
+
+#[test]
+fn test_read_terminated_invalid_block() {
+    let mut input = &b"#2X1\nNEXT\n"[..];
+    assert_eq!(
+        read_terminated(&mut input).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(read_terminated(&mut input).unwrap(), b"NEXT\n");
+}
+
+/// Byte stream that returns canned chunks and errors
+#[cfg(test)]
+struct Chunks(std::collections::VecDeque<io::Result<&'static [u8]>>);
+
+#[cfg(test)]
+impl io::Read for Chunks {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.pop_front() {
+            Some(Ok(chunk)) => {
+                buf[..chunk.len()].copy_from_slice(chunk);
+                Ok(chunk.len())
+            }
+            Some(Err(err)) => Err(err),
+            None => Ok(0),
+        }
+    }
+}
+
+#[test]
+fn test_message_reader_timeout() {
+    let mut input = io::BufReader::new(Chunks(
+        vec![
+            Ok(&b"1.5,\"a"[..]),
+            Err(io::ErrorKind::TimedOut.into()),
+            Ok(&b"\nb\"\n#11\n\n"[..]),
+        ]
+        .into(),
+    ));
+    let mut reader = MessageReader::default();
+    assert_eq!(
+        reader.read(&mut input).unwrap_err().kind(),
+        io::ErrorKind::TimedOut
+    );
+    assert_eq!(reader.read(&mut input).unwrap(), b"1.5,\"a\nb\"\n");
+    assert_eq!(reader.read(&mut input).unwrap(), b"#11\n\n");
+}
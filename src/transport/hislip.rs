@@ -0,0 +1,613 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    convert::TryFrom,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::Transport;
+use crate::{status::StatusByte, Terminator};
+
+const INITIALIZE: u8 = 0;
+const INITIALIZE_RESPONSE: u8 = 1;
+const FATAL_ERROR: u8 = 2;
+const ERROR: u8 = 3;
+const ASYNC_LOCK: u8 = 4;
+const ASYNC_LOCK_RESPONSE: u8 = 5;
+const DATA: u8 = 6;
+const DATA_END: u8 = 7;
+const DEVICE_CLEAR_COMPLETE: u8 = 8;
+const DEVICE_CLEAR_ACKNOWLEDGE: u8 = 9;
+const TRIGGER: u8 = 12;
+const INTERRUPTED: u8 = 13;
+const ASYNC_INTERRUPTED: u8 = 14;
+const ASYNC_MAXIMUM_MESSAGE_SIZE: u8 = 15;
+const ASYNC_MAXIMUM_MESSAGE_SIZE_RESPONSE: u8 = 16;
+const ASYNC_INITIALIZE: u8 = 17;
+const ASYNC_INITIALIZE_RESPONSE: u8 = 18;
+const ASYNC_DEVICE_CLEAR: u8 = 19;
+const ASYNC_SERVICE_REQUEST: u8 = 20;
+const ASYNC_STATUS_QUERY: u8 = 21;
+const ASYNC_STATUS_RESPONSE: u8 = 22;
+const ASYNC_DEVICE_CLEAR_ACKNOWLEDGE: u8 = 23;
+
+/// Protocol version 1.0
+const PROTOCOL_VERSION: u16 = 0x0100;
+/// Vendor ID reserved for clients that don't have one
+const VENDOR_ID: u16 = u16::from_be_bytes(*b"ZZ");
+/// Message ID of the first message after initialization or device clear
+const INITIAL_MESSAGE_ID: u32 = 0xffff_ff00;
+/// Largest message the client accepts
+const MAX_MESSAGE_SIZE: u64 = 1 << 20;
+const HEADER_LEN: usize = 16;
+
+/// Single HiSLIP message
+///
+/// Reference: IVI-6.1: 2.5 - Message Format
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Frame {
+    kind: u8,
+    control: u8,
+    param: u32,
+    payload: Vec<u8>,
+}
+
+fn send_frame<W: Write>(
+    w: &mut W,
+    kind: u8,
+    control: u8,
+    param: u32,
+    payload: &[u8],
+) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.extend_from_slice(b"HS");
+    buf.push(kind);
+    buf.push(control);
+    buf.extend_from_slice(&param.to_be_bytes());
+    buf.extend_from_slice(&(payload.len() as u64).to_be_bytes());
+    buf.extend_from_slice(payload);
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+fn recv_frame<R: Read>(r: &mut R) -> io::Result<Frame> {
+    let mut header = [0; HEADER_LEN];
+    r.read_exact(&mut header)?;
+    if &header[..2] != b"HS" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "invalid HiSLIP message prologue",
+        ));
+    }
+    let mut param = [0; 4];
+    param.copy_from_slice(&header[4..8]);
+    let mut len = [0; 8];
+    len.copy_from_slice(&header[8..16]);
+    let len = u64::from_be_bytes(len);
+    if len > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "HiSLIP message is too large",
+        ));
+    }
+    let mut payload = vec![0; len as usize];
+    r.read_exact(&mut payload)?;
+    Ok(Frame {
+        kind: header[2],
+        control: header[3],
+        param: u32::from_be_bytes(param),
+        payload,
+    })
+}
+
+/// Converts error messages sent by the server to I/O errors
+fn server_error(frame: &Frame) -> io::Error {
+    let message = String::from_utf8_lossy(&frame.payload);
+    let kind = match frame.kind {
+        FATAL_ERROR => "fatal error",
+        _ => "error",
+    };
+    io::Error::other(format!("HiSLIP {} {}: {}", kind, frame.control, message))
+}
+
+fn unexpected(frame: &Frame) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected HiSLIP message type {}", frame.kind),
+    )
+}
+
+/// Result of a lock request
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LockResult {
+    /// Lock was not granted before the timeout
+    Failure,
+    /// Exclusive lock was granted
+    Exclusive,
+    /// Shared lock was granted
+    Shared,
+}
+
+/// Interprets the AsyncLockResponse control code of a lock request
+///
+/// Code 2 (success, shared lock) is only sent in response to a lock release,
+/// so a granted shared lock is reported with code 1 like an exclusive one.
+///
+/// Reference: IVI-6.1: 6.5 - Lock Transaction
+fn lock_result(control: u8, shared: bool) -> io::Result<LockResult> {
+    match control {
+        0 => Ok(LockResult::Failure),
+        1 if shared => Ok(LockResult::Shared),
+        1 => Ok(LockResult::Exclusive),
+        3 => Err(io::Error::other("HiSLIP lock request was invalid")),
+        control => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unexpected HiSLIP lock response {}", control),
+        )),
+    }
+}
+
+#[test]
+fn test_lock_result() {
+    assert_eq!(lock_result(0, false).unwrap(), LockResult::Failure);
+    assert_eq!(lock_result(0, true).unwrap(), LockResult::Failure);
+    assert_eq!(lock_result(1, false).unwrap(), LockResult::Exclusive);
+    assert_eq!(lock_result(1, true).unwrap(), LockResult::Shared);
+    assert_eq!(
+        lock_result(2, true).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(
+        lock_result(3, false).unwrap_err().kind(),
+        io::ErrorKind::Other
+    );
+}
+
+/// HiSLIP connection to an instrument, using a synchronous and an asynchronous channel
+///
+/// Reference: IVI-6.1: High-Speed LAN Instrument Protocol (HiSLIP)
+#[derive(Debug)]
+pub struct HislipTransport {
+    sync: TcpStream,
+    async_: TcpStream,
+    session_id: u16,
+    overlapped: bool,
+    message_id: u32,
+    rmt_delivered: bool,
+    max_message_size: u64,
+    srq_pending: bool,
+}
+
+impl HislipTransport {
+    /// Port registered for HiSLIP
+    pub const DEFAULT_PORT: u16 = 4880;
+    /// Sub-address used by most instruments
+    pub const DEFAULT_SUB_ADDRESS: &'static str = "hislip0";
+
+    /// Connects to an instrument using the default port
+    pub fn connect(
+        host: &str,
+        sub_address: &str,
+        timeout: Duration,
+    ) -> io::Result<HislipTransport> {
+        let mut last_err = None;
+        for addr in (host, HislipTransport::DEFAULT_PORT).to_socket_addrs()? {
+            match HislipTransport::connect_addr(addr, sub_address, timeout) {
+                Ok(transport) => return Ok(transport),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+        }))
+    }
+    /// Connects to an instrument and initializes both channels
+    ///
+    /// Reference: IVI-6.1: 6.1 - Initialization Transaction
+    pub fn connect_addr(
+        addr: SocketAddr,
+        sub_address: &str,
+        timeout: Duration,
+    ) -> io::Result<HislipTransport> {
+        let mut sync = connect_channel(addr, timeout)?;
+        let param = u32::from(PROTOCOL_VERSION) << 16 | u32::from(VENDOR_ID);
+        send_frame(&mut sync, INITIALIZE, 0, param, sub_address.as_bytes())?;
+        let response = recv_frame(&mut sync)?;
+        match response.kind {
+            INITIALIZE_RESPONSE => (),
+            FATAL_ERROR | ERROR => return Err(server_error(&response)),
+            _ => return Err(unexpected(&response)),
+        }
+        let session_id = response.param as u16;
+        let overlapped = response.control & 0x01 != 0;
+
+        let mut async_ = connect_channel(addr, timeout)?;
+        send_frame(&mut async_, ASYNC_INITIALIZE, 0, u32::from(session_id), &[])?;
+        let response = recv_frame(&mut async_)?;
+        match response.kind {
+            ASYNC_INITIALIZE_RESPONSE => (),
+            FATAL_ERROR | ERROR => return Err(server_error(&response)),
+            _ => return Err(unexpected(&response)),
+        }
+
+        let mut transport = HislipTransport {
+            sync,
+            async_,
+            session_id,
+            overlapped,
+            message_id: INITIAL_MESSAGE_ID,
+            rmt_delivered: false,
+            max_message_size: MAX_MESSAGE_SIZE,
+            srq_pending: false,
+        };
+        let response = transport.async_request(
+            ASYNC_MAXIMUM_MESSAGE_SIZE,
+            0,
+            0,
+            &MAX_MESSAGE_SIZE.to_be_bytes(),
+            ASYNC_MAXIMUM_MESSAGE_SIZE_RESPONSE,
+        )?;
+        if let Ok(size) = <[u8; 8]>::try_from(&response.payload[..]) {
+            transport.max_message_size = u64::from_be_bytes(size);
+        }
+        Ok(transport)
+    }
+    /// Returns the session ID assigned by the server
+    pub fn session_id(&self) -> u16 {
+        self.session_id
+    }
+    /// Returns true if the server uses the overlapped mode
+    pub fn is_overlapped(&self) -> bool {
+        self.overlapped
+    }
+    /// Returns the largest message the server accepts
+    pub fn max_message_size(&self) -> u64 {
+        self.max_message_size
+    }
+    /// Sets the read and write timeout of both channels, or disables it if `None`
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        for stream in &[&self.sync, &self.async_] {
+            stream.set_read_timeout(timeout)?;
+            stream.set_write_timeout(timeout)?;
+        }
+        Ok(())
+    }
+    /// Reads the status byte using the asynchronous channel
+    ///
+    /// Reference: IVI-6.1: 6.12 - Status Query Transaction
+    pub fn read_stb(&mut self) -> io::Result<StatusByte> {
+        let control = self.rmt_delivered as u8;
+        let param = self.message_id.wrapping_sub(2);
+        let response = self.async_request(
+            ASYNC_STATUS_QUERY,
+            control,
+            param,
+            &[],
+            ASYNC_STATUS_RESPONSE,
+        )?;
+        self.rmt_delivered = false;
+        Ok(StatusByte(response.control))
+    }
+    /// Sends a trigger message
+    ///
+    /// Reference: IVI-6.1: 6.10 - Trigger Message
+    pub fn trigger(&mut self) -> io::Result<()> {
+        let control = self.rmt_delivered as u8;
+        send_frame(&mut self.sync, TRIGGER, control, self.message_id, &[])?;
+        self.rmt_delivered = false;
+        self.message_id = self.message_id.wrapping_add(2);
+        Ok(())
+    }
+    /// Performs a device clear, discarding any pending messages
+    ///
+    /// Reference: IVI-6.1: 6.13 - Device Clear Transaction
+    pub fn clear(&mut self) -> io::Result<()> {
+        let ack = self.async_request(
+            ASYNC_DEVICE_CLEAR,
+            0,
+            0,
+            &[],
+            ASYNC_DEVICE_CLEAR_ACKNOWLEDGE,
+        )?;
+        send_frame(&mut self.sync, DEVICE_CLEAR_COMPLETE, ack.control, 0, &[])?;
+        loop {
+            let frame = recv_frame(&mut self.sync)?;
+            match frame.kind {
+                DEVICE_CLEAR_ACKNOWLEDGE => {
+                    self.overlapped = frame.control & 0x01 != 0;
+                    break;
+                }
+                FATAL_ERROR | ERROR => return Err(server_error(&frame)),
+                // Pending data and interrupted notifications are discarded
+                _ => (),
+            }
+        }
+        self.message_id = INITIAL_MESSAGE_ID;
+        self.rmt_delivered = false;
+        Ok(())
+    }
+    /// Requests an exclusive lock, or a shared lock if `shared_lock` is given
+    ///
+    /// Reference: IVI-6.1: 6.5 - Lock Transaction
+    pub fn lock(&mut self, timeout: Duration, shared_lock: Option<&str>) -> io::Result<LockResult> {
+        let timeout = u32::try_from(timeout.as_millis()).unwrap_or(u32::MAX);
+        let payload = shared_lock.unwrap_or("").as_bytes();
+        let response = self.async_request(ASYNC_LOCK, 1, timeout, payload, ASYNC_LOCK_RESPONSE)?;
+        lock_result(response.control, shared_lock.is_some())
+    }
+    /// Releases a lock held by this client
+    pub fn unlock(&mut self) -> io::Result<()> {
+        let param = self.message_id.wrapping_sub(2);
+        let response = self.async_request(ASYNC_LOCK, 0, param, &[], ASYNC_LOCK_RESPONSE)?;
+        match response.control {
+            1 | 2 => Ok(()),
+            _ => Err(io::Error::other("HiSLIP lock release failed")),
+        }
+    }
+    /// Returns true if a service request was received, and clears the pending flag
+    pub fn take_srq(&mut self) -> bool {
+        let pending = self.srq_pending;
+        self.srq_pending = false;
+        pending
+    }
+    /// Waits for a service request on the asynchronous channel
+    ///
+    /// Reference: IVI-6.1: 6.11 - Service Request
+    pub fn wait_srq(&mut self, timeout: Duration) -> io::Result<StatusByte> {
+        if !self.take_srq() {
+            let previous = self.async_.read_timeout()?;
+            self.async_.set_read_timeout(Some(timeout))?;
+            let result = loop {
+                match recv_frame(&mut self.async_) {
+                    Ok(frame) if frame.kind == ASYNC_SERVICE_REQUEST => break Ok(()),
+                    Ok(frame) if frame.kind == FATAL_ERROR || frame.kind == ERROR => {
+                        break Err(server_error(&frame))
+                    }
+                    Ok(_) => (),
+                    Err(err) => break Err(err),
+                }
+            };
+            self.async_.set_read_timeout(previous)?;
+            result?;
+        }
+        self.read_stb()
+    }
+    /// Sends a request on the asynchronous channel and waits for its response
+    fn async_request(
+        &mut self,
+        kind: u8,
+        control: u8,
+        param: u32,
+        payload: &[u8],
+        response_kind: u8,
+    ) -> io::Result<Frame> {
+        send_frame(&mut self.async_, kind, control, param, payload)?;
+        loop {
+            let frame = recv_frame(&mut self.async_)?;
+            match frame.kind {
+                kind if kind == response_kind => return Ok(frame),
+                ASYNC_SERVICE_REQUEST => self.srq_pending = true,
+                ASYNC_INTERRUPTED => (),
+                FATAL_ERROR | ERROR => return Err(server_error(&frame)),
+                _ => return Err(unexpected(&frame)),
+            }
+        }
+    }
+}
+
+fn connect_channel(addr: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_nodelay(true)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    Ok(stream)
+}
+
+impl Transport for HislipTransport {
+    /// Sends the message as `Data` messages followed by a final `DataEnd` message
+    ///
+    /// Reference: IVI-6.1: 6.2 - Data Transfer Messages
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        let max_payload = (self.max_message_size as usize)
+            .saturating_sub(HEADER_LEN)
+            .max(1);
+        let mut chunks = message.chunks(max_payload).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let kind = if chunks.peek().is_none() {
+                DATA_END
+            } else {
+                DATA
+            };
+            let control = self.rmt_delivered as u8;
+            send_frame(&mut self.sync, kind, control, self.message_id, chunk)?;
+            self.rmt_delivered = false;
+            if kind == DATA_END {
+                break;
+            }
+        }
+        self.message_id = self.message_id.wrapping_add(2);
+        Ok(())
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        loop {
+            let frame = recv_frame(&mut self.sync)?;
+            match frame.kind {
+                DATA => message.extend_from_slice(&frame.payload),
+                DATA_END => {
+                    message.extend_from_slice(&frame.payload);
+                    self.rmt_delivered = true;
+                    return Ok(message);
+                }
+                // Partial responses are discarded if the server interrupts them
+                INTERRUPTED => message.clear(),
+                FATAL_ERROR | ERROR => return Err(server_error(&frame)),
+                _ => return Err(unexpected(&frame)),
+            }
+        }
+    }
+    fn terminator(&self) -> Terminator {
+        Terminator::NewlineEnd
+    }
+}
+
+/// Minimal stand-in for a HiSLIP server that accepts a single session
+#[cfg(test)]
+fn instrument() -> (SocketAddr, std::thread::JoinHandle<Vec<Frame>>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let (mut sync, _) = listener.accept().unwrap();
+        let init = recv_frame(&mut sync).unwrap();
+        assert_eq!(init.kind, INITIALIZE);
+        assert_eq!(init.param, 0x0100_5a5a);
+        assert_eq!(init.payload, b"hislip0");
+        send_frame(&mut sync, INITIALIZE_RESPONSE, 0, 0x0100_1234, &[]).unwrap();
+        let (mut async_, _) = listener.accept().unwrap();
+        let init = recv_frame(&mut async_).unwrap();
+        assert_eq!((init.kind, init.param), (ASYNC_INITIALIZE, 0x1234));
+        send_frame(&mut async_, ASYNC_INITIALIZE_RESPONSE, 0, 0, &[]).unwrap();
+        let size = recv_frame(&mut async_).unwrap();
+        assert_eq!(size.kind, ASYNC_MAXIMUM_MESSAGE_SIZE);
+        // Small maximum message size to exercise splitting messages
+        let max_size = (HEADER_LEN as u64 + 8).to_be_bytes();
+        send_frame(
+            &mut async_,
+            ASYNC_MAXIMUM_MESSAGE_SIZE_RESPONSE,
+            0,
+            0,
+            &max_size,
+        )
+        .unwrap();
+
+        let mut async_writer = async_.try_clone().unwrap();
+        let async_thread = std::thread::spawn(move || {
+            while let Ok(frame) = recv_frame(&mut async_) {
+                match frame.kind {
+                    ASYNC_STATUS_QUERY => {
+                        send_frame(&mut async_, ASYNC_STATUS_RESPONSE, 0x50, 0, &[]).unwrap()
+                    }
+                    ASYNC_LOCK if frame.control == 1 => {
+                        assert_eq!(frame.param, 500);
+                        send_frame(&mut async_, ASYNC_LOCK_RESPONSE, 1, 0, &[]).unwrap()
+                    }
+                    ASYNC_LOCK => send_frame(&mut async_, ASYNC_LOCK_RESPONSE, 1, 0, &[]).unwrap(),
+                    ASYNC_DEVICE_CLEAR => {
+                        send_frame(&mut async_, ASYNC_DEVICE_CLEAR_ACKNOWLEDGE, 0, 0, &[]).unwrap()
+                    }
+                    _ => panic!("unexpected message {:?}", frame),
+                }
+            }
+        });
+
+        let mut frames = Vec::new();
+        let mut message = Vec::new();
+        while let Ok(frame) = recv_frame(&mut sync) {
+            match frame.kind {
+                DATA => message.extend_from_slice(&frame.payload),
+                DATA_END => {
+                    message.extend_from_slice(&frame.payload);
+                    if message == b"*IDN?\n" {
+                        let id = frame.param;
+                        send_frame(&mut sync, DATA, 0, id, b"ACME,X1,").unwrap();
+                        send_frame(&mut sync, DATA_END, 0, id, b"1234,1.0\n").unwrap();
+                    }
+                    message.clear();
+                }
+                TRIGGER => send_frame(&mut async_writer, ASYNC_SERVICE_REQUEST, 0, 0, &[]).unwrap(),
+                DEVICE_CLEAR_COMPLETE => {
+                    send_frame(&mut sync, DEVICE_CLEAR_ACKNOWLEDGE, 0, 0, &[]).unwrap()
+                }
+                _ => panic!("unexpected message {:?}", frame),
+            }
+            frames.push(Frame {
+                payload: Vec::new(),
+                ..frame
+            });
+        }
+        drop(async_writer);
+        async_thread.join().unwrap();
+        frames
+    });
+    (addr, handle)
+}
+
+#[test]
+fn test_hislip_query() {
+    use crate::common::{IdnQuery, Rst};
+    let (addr, handle) = instrument();
+    let mut transport =
+        HislipTransport::connect_addr(addr, "hislip0", Duration::from_secs(5)).unwrap();
+    assert_eq!(transport.session_id(), 0x1234);
+    assert!(!transport.is_overlapped());
+    assert_eq!(transport.max_message_size(), 24);
+    transport.send(Rst).unwrap();
+    let idn = transport.query(IdnQuery).unwrap();
+    assert_eq!(idn.serial_number, "1234");
+    transport.send(Rst).unwrap();
+    drop(transport);
+    let frames = handle.join().unwrap();
+    let summary: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.kind, frame.control, frame.param))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (DATA_END, 0, 0xffff_ff00),
+            (DATA_END, 0, 0xffff_ff02),
+            // RMT-delivered is set after a complete response was received
+            (DATA_END, 1, 0xffff_ff04),
+        ]
+    );
+}
+
+#[test]
+fn test_hislip_split_message() {
+    let (addr, handle) = instrument();
+    let mut transport =
+        HislipTransport::connect_addr(addr, "hislip0", Duration::from_secs(5)).unwrap();
+    transport.write_message(b"DATA 1,2,3,4\n").unwrap();
+    drop(transport);
+    let frames = handle.join().unwrap();
+    let kinds: Vec<_> = frames.iter().map(|frame| frame.kind).collect();
+    assert_eq!(kinds, vec![DATA, DATA_END]);
+    assert!(frames.iter().all(|frame| frame.param == 0xffff_ff00));
+}
+
+#[test]
+fn test_hislip_async_operations() {
+    let (addr, handle) = instrument();
+    let mut transport =
+        HislipTransport::connect_addr(addr, "hislip0", Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        transport.read_stb().unwrap(),
+        StatusByte::MAV | StatusByte::RQS
+    );
+    assert_eq!(
+        transport.lock(Duration::from_millis(500), None).unwrap(),
+        LockResult::Exclusive
+    );
+    transport.unlock().unwrap();
+    transport.trigger().unwrap();
+    assert_eq!(
+        transport.wait_srq(Duration::from_secs(5)).unwrap(),
+        StatusByte::MAV | StatusByte::RQS
+    );
+    transport.clear().unwrap();
+    drop(transport);
+    let frames = handle.join().unwrap();
+    let summary: Vec<_> = frames
+        .iter()
+        .map(|frame| (frame.kind, frame.param))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![(TRIGGER, 0xffff_ff00), (DEVICE_CLEAR_COMPLETE, 0)]
+    );
+}
@@ -0,0 +1,417 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{error::Error, fmt, io, str::FromStr, time::Duration};
+
+use super::{HislipTransport, TcpTransport, Transport, Vxi11Transport};
+
+/// Error returned when a VISA resource string can't be parsed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseResourceError {
+    pub resource: String,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseResourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid resource {:?}: {}", self.resource, self.reason)
+    }
+}
+
+impl Error for ParseResourceError {}
+
+impl From<ParseResourceError> for io::Error {
+    fn from(err: ParseResourceError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+/// VISA resource name identifying an instrument and the way to reach it
+///
+/// Interface types and resource classes are case-insensitive, and are
+/// formatted in upper case. The board number is kept as written, so
+/// `TCPIP::host::INSTR` and `TCPIP0::host::INSTR` both round-trip.
+///
+/// Reference: VPP-4.3: 4.3.1.1 - Address String Grammar
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResourceName {
+    /// `TCPIP[board]::host[::device]::INSTR`, using VXI-11 or HiSLIP depending on the device name
+    TcpipInstr {
+        board: Option<u16>,
+        host: String,
+        device: Option<String>,
+    },
+    /// `TCPIP[board]::host::port::SOCKET`
+    TcpipSocket {
+        board: Option<u16>,
+        host: String,
+        port: u16,
+    },
+    /// `USB[board]::vendor::product::serial[::interface]::INSTR`
+    UsbInstr {
+        board: Option<u16>,
+        vendor_id: u16,
+        product_id: u16,
+        serial_number: String,
+        interface: Option<u8>,
+    },
+    /// `ASRL[board]::INSTR`, or `ASRL<device path>::INSTR`
+    AsrlInstr { port: String },
+}
+
+impl ResourceName {
+    /// Returns true if the resource uses the HiSLIP protocol
+    pub fn is_hislip(&self) -> bool {
+        match self {
+            ResourceName::TcpipInstr {
+                device: Some(device),
+                ..
+            } => device.to_ascii_lowercase().starts_with("hislip"),
+            _ => false,
+        }
+    }
+    /// Returns the network host of a TCP/IP resource, without the brackets of an IPv6 address
+    pub fn host(&self) -> Option<&str> {
+        match self {
+            ResourceName::TcpipInstr { host, .. } | ResourceName::TcpipSocket { host, .. } => {
+                Some(host.trim_start_matches('[').trim_end_matches(']'))
+            }
+            _ => None,
+        }
+    }
+    /// Opens a transport for the resource
+    ///
+    /// USB resources need a USB stack, so they are opened by wrapping a
+    /// `UsbtmcDevice` in a `UsbtmcTransport` instead. Serial resources need
+    /// their line settings configured, so they are opened by wrapping the
+    /// configured port in a `SerialTransport`.
+    pub fn open(&self, timeout: Duration) -> io::Result<Box<dyn Transport + Send>> {
+        match self {
+            ResourceName::TcpipInstr { device, .. } if self.is_hislip() => {
+                let host = self.host().unwrap_or_default();
+                let device = device
+                    .as_deref()
+                    .unwrap_or(HislipTransport::DEFAULT_SUB_ADDRESS);
+                Ok(Box::new(HislipTransport::connect(host, device, timeout)?))
+            }
+            ResourceName::TcpipInstr { device, .. } => {
+                let host = self.host().unwrap_or_default();
+                let device = device.as_deref().unwrap_or(Vxi11Transport::DEFAULT_DEVICE);
+                Ok(Box::new(Vxi11Transport::connect(host, device, timeout)?))
+            }
+            ResourceName::TcpipSocket { port, .. } => {
+                let host = self.host().unwrap_or_default();
+                Ok(Box::new(TcpTransport::connect((host, *port), timeout)?))
+            }
+            ResourceName::UsbInstr { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "USB resources require a UsbtmcDevice implementation",
+            )),
+            ResourceName::AsrlInstr { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "serial resources require a configured port in a SerialTransport",
+            )),
+        }
+    }
+}
+
+/// Parses a resource string and opens a transport for it
+pub fn open(resource: &str, timeout: Duration) -> io::Result<Box<dyn Transport + Send>> {
+    resource.parse::<ResourceName>()?.open(timeout)
+}
+
+/// Splits a resource string at `::`, except inside a bracketed IPv6 address
+fn split_tokens(resource: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let bytes = resource.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'[' => depth += 1,
+            b']' => depth -= 1,
+            b':' if depth == 0 && bytes.get(idx + 1) == Some(&b':') => {
+                tokens.push(&resource[start..idx]);
+                idx += 2;
+                start = idx;
+                continue;
+            }
+            _ => (),
+        }
+        idx += 1;
+    }
+    tokens.push(&resource[start..]);
+    tokens
+}
+
+/// Splits an interface token into its type prefix and optional board number
+fn parse_interface<'a>(token: &'a str, prefix: &str) -> Option<Result<Option<u16>, &'a str>> {
+    if token.len() < prefix.len() || !token[..prefix.len()].eq_ignore_ascii_case(prefix) {
+        return None;
+    }
+    let board = &token[prefix.len()..];
+    if board.is_empty() {
+        Some(Ok(None))
+    } else {
+        Some(board.parse().map(Some).map_err(|_| board))
+    }
+}
+
+fn parse_id(token: &str) -> Option<u16> {
+    match token.get(..2) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("0x") => {
+            u16::from_str_radix(&token[2..], 16).ok()
+        }
+        _ => token.parse().ok(),
+    }
+}
+
+impl FromStr for ResourceName {
+    type Err = ParseResourceError;
+    fn from_str(resource: &str) -> Result<Self, Self::Err> {
+        let err = |reason| ParseResourceError {
+            resource: resource.to_owned(),
+            reason,
+        };
+        let tokens = split_tokens(resource);
+        let (interface, rest) = tokens.split_first().ok_or_else(|| err("empty resource"))?;
+        let (class, fields) = rest
+            .split_last()
+            .ok_or_else(|| err("missing resource class"))?;
+        let class = class.to_ascii_uppercase();
+        if fields.iter().any(|field| field.is_empty()) {
+            return Err(err("empty field"));
+        }
+
+        if let Some(board) = parse_interface(interface, "TCPIP") {
+            let board = board.map_err(|_| err("invalid board number"))?;
+            return match (class.as_str(), fields) {
+                ("INSTR", [host]) => Ok(ResourceName::TcpipInstr {
+                    board,
+                    host: host.to_string(),
+                    device: None,
+                }),
+                ("INSTR", [host, device]) => Ok(ResourceName::TcpipInstr {
+                    board,
+                    host: host.to_string(),
+                    device: Some(device.to_string()),
+                }),
+                ("SOCKET", [host, port]) => Ok(ResourceName::TcpipSocket {
+                    board,
+                    host: host.to_string(),
+                    port: port.parse().map_err(|_| err("invalid port"))?,
+                }),
+                ("INSTR", _) | ("SOCKET", _) => Err(err("unexpected number of fields")),
+                _ => Err(err("unsupported resource class")),
+            };
+        }
+        if let Some(board) = parse_interface(interface, "USB") {
+            let board = board.map_err(|_| err("invalid board number"))?;
+            if class != "INSTR" {
+                return Err(err("unsupported resource class"));
+            }
+            let (vendor, product, serial, interface) = match fields {
+                [vendor, product, serial] => (vendor, product, serial, None),
+                [vendor, product, serial, interface] => {
+                    let interface = interface
+                        .parse()
+                        .map_err(|_| err("invalid interface number"))?;
+                    (vendor, product, serial, Some(interface))
+                }
+                _ => return Err(err("unexpected number of fields")),
+            };
+            return Ok(ResourceName::UsbInstr {
+                board,
+                vendor_id: parse_id(vendor).ok_or_else(|| err("invalid vendor ID"))?,
+                product_id: parse_id(product).ok_or_else(|| err("invalid product ID"))?,
+                serial_number: serial.to_string(),
+                interface,
+            });
+        }
+        if interface.len() > 4 && interface[..4].eq_ignore_ascii_case("ASRL") {
+            if class != "INSTR" {
+                return Err(err("unsupported resource class"));
+            }
+            if !fields.is_empty() {
+                return Err(err("unexpected number of fields"));
+            }
+            return Ok(ResourceName::AsrlInstr {
+                port: interface[4..].to_owned(),
+            });
+        }
+        Err(err("unsupported interface type"))
+    }
+}
+
+fn write_board(f: &mut fmt::Formatter<'_>, board: Option<u16>) -> fmt::Result {
+    match board {
+        Some(board) => write!(f, "{}", board),
+        None => Ok(()),
+    }
+}
+
+impl fmt::Display for ResourceName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResourceName::TcpipInstr {
+                board,
+                host,
+                device,
+            } => {
+                f.write_str("TCPIP")?;
+                write_board(f, *board)?;
+                write!(f, "::{}", host)?;
+                if let Some(device) = device {
+                    write!(f, "::{}", device)?;
+                }
+                f.write_str("::INSTR")
+            }
+            ResourceName::TcpipSocket { board, host, port } => {
+                f.write_str("TCPIP")?;
+                write_board(f, *board)?;
+                write!(f, "::{}::{}::SOCKET", host, port)
+            }
+            ResourceName::UsbInstr {
+                board,
+                vendor_id,
+                product_id,
+                serial_number,
+                interface,
+            } => {
+                f.write_str("USB")?;
+                write_board(f, *board)?;
+                write!(
+                    f,
+                    "::0x{:04X}::0x{:04X}::{}",
+                    vendor_id, product_id, serial_number
+                )?;
+                if let Some(interface) = interface {
+                    write!(f, "::{}", interface)?;
+                }
+                f.write_str("::INSTR")
+            }
+            ResourceName::AsrlInstr { port } => write!(f, "ASRL{}::INSTR", port),
+        }
+    }
+}
+
+#[test]
+fn test_resource_round_trip() {
+    for resource in &[
+        "TCPIP0::192.168.1.10::inst0::INSTR",
+        "TCPIP::host::5025::SOCKET",
+        "TCPIP::host::hislip0::INSTR",
+        "TCPIP1::scope.local::INSTR",
+        "TCPIP0::[fe80::1]::5025::SOCKET",
+        "USB0::0x0957::0x1796::MY12345678::INSTR",
+        "USB::0x1AB1::0x04CE::DS1ZA1::0::INSTR",
+        "ASRL/dev/ttyUSB0::INSTR",
+        "ASRL1::INSTR",
+    ] {
+        let name = resource.parse::<ResourceName>().unwrap();
+        assert_eq!(name.to_string(), *resource);
+    }
+}
+
+#[test]
+fn test_resource_parse() {
+    assert_eq!(
+        "tcpip0::192.168.1.10::inst0::instr".parse(),
+        Ok(ResourceName::TcpipInstr {
+            board: Some(0),
+            host: "192.168.1.10".to_owned(),
+            device: Some("inst0".to_owned()),
+        })
+    );
+    assert_eq!(
+        "USB0::2391::6038::SN::INSTR".parse(),
+        Ok(ResourceName::UsbInstr {
+            board: Some(0),
+            vendor_id: 0x0957,
+            product_id: 0x1796,
+            serial_number: "SN".to_owned(),
+            interface: None,
+        })
+    );
+    assert_eq!(
+        "TCPIP0::[::1]::5025::SOCKET".parse(),
+        Ok(ResourceName::TcpipSocket {
+            board: Some(0),
+            host: "[::1]".to_owned(),
+            port: 5025,
+        })
+    );
+    let name = "TCPIP0::[fe80::1]::inst0::INSTR"
+        .parse::<ResourceName>()
+        .unwrap();
+    assert_eq!(
+        name,
+        ResourceName::TcpipInstr {
+            board: Some(0),
+            host: "[fe80::1]".to_owned(),
+            device: Some("inst0".to_owned()),
+        }
+    );
+    assert_eq!(name.host(), Some("fe80::1"));
+    assert_eq!(
+        "TCPIP0::[::1]::hislip0::INSTR"
+            .parse::<ResourceName>()
+            .unwrap()
+            .host(),
+        Some("::1")
+    );
+    assert_eq!("ASRL1::INSTR".parse::<ResourceName>().unwrap().host(), None);
+    assert!("TCPIP::host::hislip0::INSTR"
+        .parse::<ResourceName>()
+        .unwrap()
+        .is_hislip());
+    assert!(!"TCPIP::host::INSTR"
+        .parse::<ResourceName>()
+        .unwrap()
+        .is_hislip());
+}
+
+#[test]
+fn test_resource_parse_errors() {
+    let reason = |resource: &str| resource.parse::<ResourceName>().unwrap_err().reason;
+    assert_eq!(reason("GPIB0::1::INSTR"), "unsupported interface type");
+    assert_eq!(reason("TCPIP::host::70000::SOCKET"), "invalid port");
+    assert_eq!(reason("TCPIP::host::5025"), "unsupported resource class");
+    assert_eq!(reason("TCPIPx::host::INSTR"), "invalid board number");
+    assert_eq!(
+        reason("TCPIP::host::a::b::INSTR"),
+        "unexpected number of fields"
+    );
+    assert_eq!(reason("USB::0xZZ::1::SN::INSTR"), "invalid vendor ID");
+    assert_eq!(reason("TCPIP::::INSTR"), "empty field");
+    assert_eq!(reason("INSTR"), "missing resource class");
+}
+
+#[test]
+fn test_open_socket_resource() {
+    use crate::common::IdnQuery;
+    use std::io::{BufRead, BufReader, Write};
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        assert_eq!(line, "*IDN?\n");
+        (&stream).write_all(b"ACME,X1,1234,1.0\n").unwrap();
+    });
+    let resource = format!("TCPIP0::127.0.0.1::{}::SOCKET", port);
+    let mut transport = open(&resource, Duration::from_secs(5)).unwrap();
+    assert_eq!(transport.query(IdnQuery).unwrap().manufacturer, "ACME");
+    handle.join().unwrap();
+    for resource in &[
+        "USB0::1::2::SN::INSTR",
+        "ASRL/dev/null::INSTR",
+        "ASRL1::INSTR",
+    ] {
+        let err = open(resource, Duration::from_secs(1)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
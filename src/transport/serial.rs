@@ -0,0 +1,235 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{MessageReader, Transport};
+use crate::{common::OpcQuery, response, Command, TransportError};
+
+/// Serial connection to an instrument over any byte stream
+///
+/// Serial lines can't signal END, so messages are delimited by terminator
+/// characters only. Program messages are encoded with a newline and sent
+/// with the configured write terminator.
+///
+/// Reference: IEEE 488.2: 7.5 - <PROGRAM MESSAGE TERMINATOR>
+#[derive(Debug)]
+pub struct SerialTransport<S> {
+    stream: BufReader<S>,
+    reader: MessageReader,
+    partial: Vec<u8>,
+    write_terminator: Vec<u8>,
+    read_terminator: u8,
+    echo: bool,
+    pacing: Duration,
+    last_write: Option<Instant>,
+}
+
+impl<S: Read + Write> SerialTransport<S> {
+    pub fn new(stream: S) -> SerialTransport<S> {
+        SerialTransport {
+            stream: BufReader::new(stream),
+            reader: MessageReader::default(),
+            partial: Vec::new(),
+            write_terminator: b"\n".to_vec(),
+            read_terminator: b'\n',
+            echo: false,
+            pacing: Duration::from_millis(0),
+            last_write: None,
+        }
+    }
+    /// Sets the bytes sent at the end of every program message (default `\n`)
+    pub fn write_terminator(mut self, terminator: &[u8]) -> SerialTransport<S> {
+        self.write_terminator = terminator.to_vec();
+        self
+    }
+    /// Sets the byte that ends every response message (default `\n`)
+    ///
+    /// Received responses are normalized to end with a newline.
+    pub fn read_terminator(mut self, terminator: u8) -> SerialTransport<S> {
+        self.read_terminator = terminator;
+        self
+    }
+    /// Enables discarding the echo of every sent program message
+    pub fn echo(mut self, enabled: bool) -> SerialTransport<S> {
+        self.echo = enabled;
+        self
+    }
+    /// Sets the minimum time between the end of a program message and the next one
+    pub fn pacing(mut self, delay: Duration) -> SerialTransport<S> {
+        self.pacing = delay;
+        self
+    }
+    /// Returns the underlying stream
+    pub fn stream(&mut self) -> &mut S {
+        self.stream.get_mut()
+    }
+    /// Returns the underlying stream, discarding any buffered input
+    pub fn into_inner(self) -> S {
+        self.stream.into_inner()
+    }
+    /// Sends a command followed by `*OPC?` and waits until the instrument has completed it
+    ///
+    /// Reference: IEEE 488.2: 12.5.3 - Operation Complete Query Active State
+    pub fn send_synchronized<C: Command>(&mut self, command: C) -> Result<(), TransportError> {
+        let mut msg = self.message();
+        msg.command(command)?;
+        msg.command(OpcQuery)?;
+        let message = msg.finish()?;
+        self.write_message(&message)?;
+        self.wait_complete()
+    }
+    /// Waits until all pending operations have completed using `*OPC?`
+    pub fn sync(&mut self) -> Result<(), TransportError> {
+        self.send(OpcQuery)?;
+        self.wait_complete()
+    }
+    fn wait_complete(&mut self) -> Result<(), TransportError> {
+        let response = self.read_message()?;
+        // *OPC? only responds once everything is complete, so the value itself is always 1
+        response::parse::<bool>(&response)?;
+        Ok(())
+    }
+    fn discard_echo(&mut self, sent: &[u8]) -> io::Result<()> {
+        let mut echo = vec![0; sent.len()];
+        self.stream.read_exact(&mut echo)?;
+        if echo != sent {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "echoed data doesn't match the sent message",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Transport for SerialTransport<S> {
+    /// Sends the message after the pacing delay, replacing its newline terminator
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        if let Some(last_write) = self.last_write {
+            let elapsed = last_write.elapsed();
+            if elapsed < self.pacing {
+                thread::sleep(self.pacing - elapsed);
+            }
+        }
+        let body = message.strip_suffix(b"\n").unwrap_or(message);
+        let mut data = Vec::with_capacity(body.len() + self.write_terminator.len());
+        data.extend_from_slice(body);
+        data.extend_from_slice(&self.write_terminator);
+        let stream = self.stream.get_mut();
+        stream.write_all(&data)?;
+        stream.flush()?;
+        self.last_write = Some(Instant::now());
+        if self.echo {
+            self.discard_echo(&data)?;
+        }
+        Ok(())
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        if self.read_terminator == b'\n' {
+            return self.reader.read(&mut self.stream);
+        }
+        // Bytes read before an error are kept in the partial message
+        self.stream
+            .read_until(self.read_terminator, &mut self.partial)?;
+        let mut message = std::mem::take(&mut self.partial);
+        match message.last_mut() {
+            Some(last) if *last == self.read_terminator => *last = b'\n',
+            _ => return Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+        Ok(message)
+    }
+}
+
+/// In-memory serial line with canned instrument output
+#[cfg(test)]
+#[derive(Debug)]
+struct MemoryLine {
+    input: io::Cursor<Vec<u8>>,
+    output: Vec<u8>,
+}
+
+#[cfg(test)]
+impl MemoryLine {
+    fn new(input: &[u8]) -> MemoryLine {
+        MemoryLine {
+            input: io::Cursor::new(input.to_vec()),
+            output: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Read for MemoryLine {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for MemoryLine {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_serial_terminators() {
+    use crate::common::{IdnQuery, Rst};
+    let line = MemoryLine::new(b"ACME,PSU,1234,1.0\r");
+    let mut transport = SerialTransport::new(line)
+        .write_terminator(b"\r\n")
+        .read_terminator(b'\r');
+    transport.send(Rst).unwrap();
+    let idn = transport.query(IdnQuery).unwrap();
+    assert_eq!(idn.model, "PSU");
+    assert_eq!(transport.stream().output, b"*RST\r\n*IDN?\r\n");
+    assert_eq!(
+        transport.read_message().unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+}
+
+#[test]
+fn test_serial_echo() {
+    use crate::common::TstQuery;
+    let line = MemoryLine::new(b"*TST?\n+0\n");
+    let mut transport = SerialTransport::new(line).echo(true);
+    assert_eq!(transport.query(TstQuery).unwrap(), 0);
+
+    let line = MemoryLine::new(b"*TST!\n");
+    let mut transport = SerialTransport::new(line).echo(true);
+    assert_eq!(
+        transport.write_message(b"*TST?\n").unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn test_serial_synchronized() {
+    use crate::common::Rst;
+    let line = MemoryLine::new(b"1\n1\n");
+    let mut transport = SerialTransport::new(line);
+    transport.send_synchronized(Rst).unwrap();
+    transport.sync().unwrap();
+    assert_eq!(transport.into_inner().output, b"*RST;*OPC?\n*OPC?\n");
+}
+
+#[test]
+fn test_serial_pacing() {
+    let line = MemoryLine::new(b"");
+    let mut transport = SerialTransport::new(line).pacing(Duration::from_millis(50));
+    let start = Instant::now();
+    transport.write_message(b"A\n").unwrap();
+    transport.write_message(b"B\n").unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(transport.stream().output, b"A\nB\n");
+}
@@ -0,0 +1,143 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    io::{self, BufReader, Write},
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::{MessageReader, Transport};
+
+/// Raw SCPI socket connection to a LAN instrument
+///
+/// Program and response messages are terminated with a newline character.
+///
+/// Reference: LXI Device Specification 2016: 17.1 - SCPI over raw socket
+#[derive(Debug)]
+pub struct TcpTransport {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    message: MessageReader,
+}
+
+impl TcpTransport {
+    /// Port used by most instruments for raw SCPI sockets
+    pub const DEFAULT_PORT: u16 = 5025;
+
+    /// Connects to an instrument, using the timeout for connecting and for all subsequent I/O
+    pub fn connect<A: ToSocketAddrs>(addr: A, timeout: Duration) -> io::Result<TcpTransport> {
+        let mut last_err = None;
+        for addr in addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, timeout) {
+                Ok(stream) => {
+                    let transport = TcpTransport::from_stream(stream)?;
+                    transport.set_timeout(Some(timeout))?;
+                    return Ok(transport);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+        }))
+    }
+    /// Wraps an already connected stream
+    pub fn from_stream(stream: TcpStream) -> io::Result<TcpTransport> {
+        // Messages are small, so Nagle's algorithm only adds latency
+        stream.set_nodelay(true)?;
+        Ok(TcpTransport {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            message: MessageReader::default(),
+        })
+    }
+    /// Sets the read and write timeout, or disables it if `None`
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.writer.set_read_timeout(timeout)?;
+        self.writer.set_write_timeout(timeout)
+    }
+    /// Returns the underlying stream
+    pub fn stream(&self) -> &TcpStream {
+        &self.writer
+    }
+    /// Shuts down the connection
+    pub fn close(self) -> io::Result<()> {
+        self.writer.shutdown(Shutdown::Both)
+    }
+}
+
+impl Transport for TcpTransport {
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        self.writer.write_all(message)?;
+        self.writer.flush()
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        self.message.read(&mut self.reader)
+    }
+}
+
+#[cfg(test)]
+fn loopback<F>(instrument: F) -> (TcpTransport, std::thread::JoinHandle<()>)
+where
+    F: FnOnce(TcpStream) + Send + 'static,
+{
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        instrument(stream);
+    });
+    let transport = TcpTransport::connect(addr, Duration::from_secs(5)).unwrap();
+    (transport, handle)
+}
+
+#[test]
+fn test_tcp_query() {
+    use crate::common::{IdnQuery, Rst};
+    use std::io::BufRead;
+    let (mut transport, handle) = loopback(|stream| {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "*RST\n");
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        assert_eq!(line, "*IDN?\n");
+        writer.write_all(b"ACME,X1,1234,1.0\n").unwrap();
+    });
+    transport.send(Rst).unwrap();
+    let idn = transport.query(IdnQuery).unwrap();
+    assert_eq!(idn.model, "X1");
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_tcp_block_response() {
+    use crate::{response::parse, Block};
+    let (mut transport, handle) = loopback(|mut stream| {
+        stream.write_all(b"#15\n\x01\n\x02\n\n").unwrap();
+    });
+    let response = transport.read_message().unwrap();
+    assert_eq!(parse::<Block>(&response), Ok(Block(b"\n\x01\n\x02\n")));
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_tcp_timeout() {
+    let (mut transport, handle) = loopback(|stream| {
+        std::thread::sleep(Duration::from_millis(200));
+        drop(stream);
+    });
+    transport
+        .set_timeout(Some(Duration::from_millis(20)))
+        .unwrap();
+    let err = transport.read_message().unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    ));
+    handle.join().unwrap();
+}
@@ -0,0 +1,423 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io;
+
+use super::Transport;
+use crate::{status::StatusByte, Terminator};
+
+const DEV_DEP_MSG_OUT: u8 = 1;
+const REQUEST_DEV_DEP_MSG_IN: u8 = 2;
+const DEV_DEP_MSG_IN: u8 = 2;
+const TRIGGER: u8 = 128;
+
+const READ_STATUS_BYTE: u8 = 128;
+const STATUS_SUCCESS: u8 = 0x01;
+
+const HEADER_LEN: usize = 12;
+
+/// USB endpoints and control requests used by a USBTMC interface
+///
+/// Implementations wrap a USB library, which keeps the framing layer free
+/// of any particular USB stack.
+pub trait UsbtmcDevice {
+    /// Writes a complete transfer to the bulk-out endpoint
+    fn bulk_out(&mut self, data: &[u8]) -> io::Result<()>;
+    /// Reads a single transfer from the bulk-in endpoint, returning its length
+    fn bulk_in(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+    /// Sends a class-specific control-in request to the interface, returning the response length
+    fn control_in(&mut self, request: u8, value: u16, buf: &mut [u8]) -> io::Result<usize>;
+    /// Reads a notification from the interrupt-in endpoint
+    ///
+    /// Returns `None` if the interface has no interrupt-in endpoint.
+    fn interrupt_in(&mut self, _buf: &mut [u8]) -> io::Result<Option<usize>> {
+        Ok(None)
+    }
+}
+
+impl<T: UsbtmcDevice + ?Sized> UsbtmcDevice for &mut T {
+    fn bulk_out(&mut self, data: &[u8]) -> io::Result<()> {
+        (**self).bulk_out(data)
+    }
+    fn bulk_in(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).bulk_in(buf)
+    }
+    fn control_in(&mut self, request: u8, value: u16, buf: &mut [u8]) -> io::Result<usize> {
+        (**self).control_in(request, value, buf)
+    }
+    fn interrupt_in(&mut self, buf: &mut [u8]) -> io::Result<Option<usize>> {
+        (**self).interrupt_in(buf)
+    }
+}
+
+/// Payload of a DEV_DEP_MSG_IN bulk-in transfer
+///
+/// Reference: USBTMC 1.0: 3.3.1 - DEV_DEP_MSG_IN
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DevDepMsgIn<'a> {
+    pub data: &'a [u8],
+    /// Last byte of the transfer is the end of the response message
+    pub eom: bool,
+    /// Transfer ended because the termination character was found
+    pub term_char: bool,
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// Stateful encoder and decoder for USBTMC and USB488 transfers
+///
+/// Reference: USBTMC 1.0: 3 - Bulk-OUT and Bulk-IN endpoints
+#[derive(Clone, Debug)]
+pub struct UsbtmcFramer {
+    tag: u8,
+    status_tag: u8,
+}
+
+impl Default for UsbtmcFramer {
+    fn default() -> Self {
+        UsbtmcFramer::new()
+    }
+}
+
+impl UsbtmcFramer {
+    pub fn new() -> UsbtmcFramer {
+        UsbtmcFramer {
+            tag: 0,
+            status_tag: 1,
+        }
+    }
+    /// Returns the bTag of the most recent bulk-out transfer
+    pub fn last_tag(&self) -> u8 {
+        self.tag
+    }
+    /// Advances bTag, which is in range 1..=255
+    fn next_tag(&mut self) -> u8 {
+        self.tag = self.tag.checked_add(1).unwrap_or(1);
+        self.tag
+    }
+    fn header(&mut self, msg_id: u8) -> Vec<u8> {
+        let tag = self.next_tag();
+        let mut header = Vec::with_capacity(HEADER_LEN);
+        header.extend_from_slice(&[msg_id, tag, !tag, 0]);
+        header
+    }
+    /// Encodes a DEV_DEP_MSG_OUT transfer, padded to a multiple of 4 bytes
+    ///
+    /// Reference: USBTMC 1.0: 3.2.1 - DEV_DEP_MSG_OUT
+    pub fn encode_message(&mut self, data: &[u8], eom: bool) -> Vec<u8> {
+        let mut transfer = self.header(DEV_DEP_MSG_OUT);
+        transfer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        transfer.extend_from_slice(&[eom as u8, 0, 0, 0]);
+        transfer.extend_from_slice(data);
+        let padded = (transfer.len() + 3) & !3;
+        transfer.resize(padded, 0);
+        transfer
+    }
+    /// Encodes a REQUEST_DEV_DEP_MSG_IN transfer
+    ///
+    /// Reference: USBTMC 1.0: 3.2.1.2 - REQUEST_DEV_DEP_MSG_IN
+    pub fn encode_request(&mut self, transfer_size: u32, term_char: Option<u8>) -> Vec<u8> {
+        let mut transfer = self.header(REQUEST_DEV_DEP_MSG_IN);
+        transfer.extend_from_slice(&transfer_size.to_le_bytes());
+        let (attributes, term_char) = match term_char {
+            Some(ch) => (0x02, ch),
+            None => (0x00, 0),
+        };
+        transfer.extend_from_slice(&[attributes, term_char, 0, 0]);
+        transfer
+    }
+    /// Encodes a USB488 TRIGGER transfer
+    ///
+    /// Reference: USB488 1.0: 3.2.1.1 - TRIGGER
+    pub fn encode_trigger(&mut self) -> Vec<u8> {
+        let mut transfer = self.header(TRIGGER);
+        transfer.resize(HEADER_LEN, 0);
+        transfer
+    }
+    /// Decodes a DEV_DEP_MSG_IN transfer sent in response to the most recent request
+    pub fn decode_response<'a>(&self, transfer: &'a [u8]) -> io::Result<DevDepMsgIn<'a>> {
+        if transfer.len() < HEADER_LEN {
+            return Err(invalid_data("truncated USBTMC header"));
+        }
+        if transfer[0] != DEV_DEP_MSG_IN {
+            return Err(invalid_data("unexpected USBTMC MsgID"));
+        }
+        if transfer[1] != self.tag || transfer[2] != !self.tag {
+            return Err(invalid_data("USBTMC bTag mismatch"));
+        }
+        let mut size = [0; 4];
+        size.copy_from_slice(&transfer[4..8]);
+        let size = u32::from_le_bytes(size) as usize;
+        let data = transfer[HEADER_LEN..]
+            .get(..size)
+            .ok_or_else(|| invalid_data("USBTMC transfer is shorter than TransferSize"))?;
+        Ok(DevDepMsgIn {
+            data,
+            eom: transfer[8] & 0x01 != 0,
+            term_char: transfer[8] & 0x02 != 0,
+        })
+    }
+    /// Advances the bTag of READ_STATUS_BYTE requests, which is in range 2..=127
+    ///
+    /// Reference: USB488 1.0: 4.3.1 - READ_STATUS_BYTE
+    pub fn next_status_tag(&mut self) -> u8 {
+        self.status_tag = if self.status_tag >= 127 {
+            2
+        } else {
+            self.status_tag + 1
+        };
+        self.status_tag
+    }
+    /// Decodes the READ_STATUS_BYTE control response and an optional interrupt-in notification
+    pub fn decode_status(
+        &self,
+        response: &[u8],
+        notification: Option<&[u8]>,
+    ) -> io::Result<StatusByte> {
+        match *response {
+            [STATUS_SUCCESS, tag, stb] if tag == self.status_tag => match notification {
+                None => Ok(StatusByte(stb)),
+                Some(&[notify1, stb]) if notify1 == 0x80 | self.status_tag => Ok(StatusByte(stb)),
+                Some(_) => Err(invalid_data("unexpected USB488 notification")),
+            },
+            [STATUS_SUCCESS, ..] => Err(invalid_data("USB488 bTag mismatch")),
+            [_, ..] => Err(io::Error::other("READ_STATUS_BYTE request failed")),
+            [] => Err(invalid_data("empty READ_STATUS_BYTE response")),
+        }
+    }
+}
+
+/// USBTMC connection to an instrument with USB488 extensions
+///
+/// Reference: USBTMC 1.0, USB488 1.0
+#[derive(Debug)]
+pub struct UsbtmcTransport<D> {
+    device: D,
+    framer: UsbtmcFramer,
+    max_transfer_size: u32,
+    term_char: Option<u8>,
+}
+
+impl<D: UsbtmcDevice> UsbtmcTransport<D> {
+    /// Transfer size used by default, which is accepted by most instruments
+    pub const DEFAULT_MAX_TRANSFER_SIZE: u32 = 4096;
+
+    pub fn new(device: D) -> UsbtmcTransport<D> {
+        UsbtmcTransport {
+            device,
+            framer: UsbtmcFramer::new(),
+            max_transfer_size: Self::DEFAULT_MAX_TRANSFER_SIZE,
+            term_char: None,
+        }
+    }
+    /// Sets the largest payload of a single transfer
+    pub fn set_max_transfer_size(&mut self, size: u32) {
+        self.max_transfer_size = size.max(1);
+    }
+    /// Makes the instrument end bulk-in transfers at the given character
+    ///
+    /// The instrument must report TermChar support in its capabilities.
+    pub fn set_term_char(&mut self, term_char: Option<u8>) {
+        self.term_char = term_char;
+    }
+    /// Returns the underlying device
+    pub fn device(&mut self) -> &mut D {
+        &mut self.device
+    }
+    /// Returns the underlying device, discarding the framing state
+    pub fn into_device(self) -> D {
+        self.device
+    }
+    /// Reads the status byte with a USB488 READ_STATUS_BYTE request
+    pub fn read_stb(&mut self) -> io::Result<StatusByte> {
+        let tag = self.framer.next_status_tag();
+        let mut response = [0; 3];
+        let len = self
+            .device
+            .control_in(READ_STATUS_BYTE, u16::from(tag), &mut response)?;
+        let mut notification = [0; 2];
+        match self.device.interrupt_in(&mut notification)? {
+            Some(n) => self
+                .framer
+                .decode_status(&response[..len], Some(&notification[..n])),
+            None => self.framer.decode_status(&response[..len], None),
+        }
+    }
+    /// Sends a USB488 TRIGGER message
+    pub fn trigger(&mut self) -> io::Result<()> {
+        let transfer = self.framer.encode_trigger();
+        self.device.bulk_out(&transfer)
+    }
+}
+
+impl<D: UsbtmcDevice> Transport for UsbtmcTransport<D> {
+    /// Sends the message in one or more transfers, setting EOM in the last one
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        let mut chunks = message.chunks(self.max_transfer_size as usize).peekable();
+        loop {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let eom = chunks.peek().is_none();
+            let transfer = self.framer.encode_message(chunk, eom);
+            self.device.bulk_out(&transfer)?;
+            if eom {
+                return Ok(());
+            }
+        }
+    }
+    /// Requests transfers until one has EOM set
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        let mut buf = vec![0; HEADER_LEN + self.max_transfer_size as usize + 3];
+        loop {
+            let request = self
+                .framer
+                .encode_request(self.max_transfer_size, self.term_char);
+            self.device.bulk_out(&request)?;
+            let len = self.device.bulk_in(&mut buf)?;
+            let response = self.framer.decode_response(&buf[..len])?;
+            message.extend_from_slice(response.data);
+            if response.eom {
+                return Ok(message);
+            }
+        }
+    }
+    fn terminator(&self) -> Terminator {
+        Terminator::NewlineEnd
+    }
+}
+
+/// In-memory USBTMC interface that responds with queued transfer payloads
+#[cfg(test)]
+#[derive(Debug, Default)]
+struct MemoryDevice {
+    written: Vec<Vec<u8>>,
+    responses: std::collections::VecDeque<(&'static [u8], bool)>,
+    stb: u8,
+}
+
+#[cfg(test)]
+impl UsbtmcDevice for MemoryDevice {
+    fn bulk_out(&mut self, data: &[u8]) -> io::Result<()> {
+        self.written.push(data.to_vec());
+        Ok(())
+    }
+    fn bulk_in(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let request = self.written.last().unwrap();
+        assert_eq!(request[0], REQUEST_DEV_DEP_MSG_IN);
+        let (data, eom) = self.responses.pop_front().unwrap();
+        let mut transfer = vec![DEV_DEP_MSG_IN, request[1], request[2], 0];
+        transfer.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        transfer.extend_from_slice(&[eom as u8, 0, 0, 0]);
+        transfer.extend_from_slice(data);
+        buf[..transfer.len()].copy_from_slice(&transfer);
+        Ok(transfer.len())
+    }
+    fn control_in(&mut self, request: u8, value: u16, buf: &mut [u8]) -> io::Result<usize> {
+        assert_eq!(request, READ_STATUS_BYTE);
+        buf[..3].copy_from_slice(&[STATUS_SUCCESS, value as u8, self.stb]);
+        Ok(3)
+    }
+}
+
+#[test]
+fn test_encode_message() {
+    let mut framer = UsbtmcFramer::new();
+    assert_eq!(
+        framer.encode_message(b"*IDN?\n", true),
+        [1, 1, 0xfe, 0, 6, 0, 0, 0, 1, 0, 0, 0, b'*', b'I', b'D', b'N', b'?', b'\n', 0, 0]
+    );
+    assert_eq!(
+        framer.encode_request(1024, Some(b'\n')),
+        [2, 2, 0xfd, 0, 0, 4, 0, 0, 2, b'\n', 0, 0]
+    );
+    assert_eq!(
+        framer.encode_trigger(),
+        [128, 3, 0xfc, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn test_tag_wraparound() {
+    let mut framer = UsbtmcFramer::new();
+    for _ in 0..255 {
+        framer.encode_trigger();
+    }
+    assert_eq!(framer.last_tag(), 255);
+    assert_eq!(framer.encode_trigger()[1..3], [1, 0xfe]);
+    for _ in 0..125 {
+        framer.next_status_tag();
+    }
+    assert_eq!(framer.next_status_tag(), 127);
+    assert_eq!(framer.next_status_tag(), 2);
+}
+
+#[test]
+fn test_decode_response() {
+    let mut framer = UsbtmcFramer::new();
+    framer.encode_request(64, None);
+    let transfer = [2, 1, 0xfe, 0, 2, 0, 0, 0, 1, 0, 0, 0, b'1', b'\n', 0, 0];
+    assert_eq!(
+        framer.decode_response(&transfer).unwrap(),
+        DevDepMsgIn {
+            data: b"1\n",
+            eom: true,
+            term_char: false
+        }
+    );
+    let mismatch = [2, 2, 0xfd, 0, 0, 0, 0, 0, 1, 0, 0, 0];
+    assert_eq!(
+        framer.decode_response(&mismatch).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    let truncated = [2, 1, 0xfe, 0, 8, 0, 0, 0, 1, 0, 0, 0, b'1'];
+    assert!(framer.decode_response(&truncated).is_err());
+}
+
+#[test]
+fn test_usbtmc_query() {
+    use crate::common::IdnQuery;
+    let mut device = MemoryDevice::default();
+    device.responses.push_back((b"ACME,X1,", false));
+    device.responses.push_back((b"1234,1.0\n", true));
+    let mut transport = UsbtmcTransport::new(&mut device);
+    let idn = transport.query(IdnQuery).unwrap();
+    assert_eq!(idn.firmware, "1.0");
+    let tags: Vec<_> = device.written.iter().map(|t| (t[0], t[1])).collect();
+    assert_eq!(tags, vec![(1, 1), (2, 2), (2, 3)]);
+}
+
+#[test]
+fn test_usbtmc_split_message() {
+    let mut device = MemoryDevice::default();
+    let mut transport = UsbtmcTransport::new(&mut device);
+    transport.set_max_transfer_size(4);
+    transport.write_message(b"*RST\n").unwrap();
+    assert_eq!(device.written.len(), 2);
+    assert_eq!(device.written[0][4..9], [4, 0, 0, 0, 0]);
+    assert_eq!(device.written[1][4..9], [1, 0, 0, 0, 1]);
+}
+
+#[test]
+fn test_usbtmc_read_stb() {
+    let mut device = MemoryDevice {
+        stb: 0x50,
+        ..MemoryDevice::default()
+    };
+    let mut transport = UsbtmcTransport::new(&mut device);
+    assert_eq!(
+        transport.read_stb().unwrap(),
+        StatusByte::MAV | StatusByte::RQS
+    );
+    let mut framer = UsbtmcFramer::new();
+    let tag = framer.next_status_tag();
+    assert_eq!(
+        framer
+            .decode_status(&[1, tag, 0], Some(&[0x80 | tag, 0x10]))
+            .unwrap(),
+        StatusByte::MAV
+    );
+    assert!(framer.decode_status(&[0x80, tag, 0], None).is_err());
+}
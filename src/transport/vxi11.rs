@@ -0,0 +1,653 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use super::Transport;
+use crate::{status::StatusByte, Terminator};
+
+/// ONC RPC program number of the portmapper
+const PORTMAPPER: u32 = 100_000;
+const PORTMAPPER_VERSION: u32 = 2;
+const PORTMAPPER_GETPORT: u32 = 3;
+const PORTMAPPER_PORT: u16 = 111;
+const IPPROTO_TCP: u32 = 6;
+
+/// ONC RPC program number of the VXI-11 core channel
+const DEVICE_CORE: u32 = 0x0607AF;
+const DEVICE_CORE_VERSION: u32 = 1;
+
+const CREATE_LINK: u32 = 10;
+const DEVICE_WRITE: u32 = 11;
+const DEVICE_READ: u32 = 12;
+const DEVICE_READSTB: u32 = 13;
+const DEVICE_TRIGGER: u32 = 14;
+const DEVICE_CLEAR: u32 = 15;
+const DEVICE_REMOTE: u32 = 16;
+const DEVICE_LOCAL: u32 = 17;
+const DEVICE_LOCK: u32 = 18;
+const DEVICE_UNLOCK: u32 = 19;
+const DESTROY_LINK: u32 = 23;
+
+const FLAG_WAITLOCK: u32 = 0x01;
+const FLAG_END: u32 = 0x08;
+const FLAG_TERMCHRSET: u32 = 0x80;
+
+const REASON_CHR: u32 = 0x02;
+const REASON_END: u32 = 0x04;
+
+/// Error code returned by a VXI-11 device
+///
+/// Reference: VXI-11: B.5 - Error Codes
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Vxi11Error(pub u32);
+
+impl Vxi11Error {
+    pub fn message(self) -> &'static str {
+        match self.0 {
+            1 => "syntax error",
+            3 => "device not accessible",
+            4 => "invalid link identifier",
+            5 => "parameter error",
+            6 => "channel not established",
+            8 => "operation not supported",
+            9 => "out of resources",
+            11 => "device locked by another link",
+            12 => "no lock held by this link",
+            15 => "I/O timeout",
+            17 => "I/O error",
+            21 => "invalid address",
+            23 => "abort",
+            29 => "channel already established",
+            _ => "unknown error",
+        }
+    }
+}
+
+impl fmt::Display for Vxi11Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "VXI-11 error {}: {}", self.0, self.message())
+    }
+}
+
+impl Error for Vxi11Error {}
+
+impl From<Vxi11Error> for io::Error {
+    fn from(err: Vxi11Error) -> io::Error {
+        let kind = match err.0 {
+            15 => io::ErrorKind::TimedOut,
+            11 | 12 => io::ErrorKind::WouldBlock,
+            4 | 6 => io::ErrorKind::NotConnected,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// XDR encoder for RPC arguments
+///
+/// Reference: RFC 4506 - XDR: External Data Representation Standard
+#[derive(Default)]
+struct XdrWriter(Vec<u8>);
+
+impl XdrWriter {
+    fn u32(mut self, value: u32) -> XdrWriter {
+        self.0.extend_from_slice(&value.to_be_bytes());
+        self
+    }
+    fn bool(self, value: bool) -> XdrWriter {
+        self.u32(value as u32)
+    }
+    fn opaque(mut self, data: &[u8]) -> XdrWriter {
+        self = self.u32(data.len() as u32);
+        self.0.extend_from_slice(data);
+        let padding = (4 - data.len() % 4) % 4;
+        self.0.extend_from_slice(&[0; 3][..padding]);
+        self
+    }
+}
+
+/// XDR decoder for RPC results
+struct XdrReader<'a>(&'a [u8]);
+
+impl<'a> XdrReader<'a> {
+    fn u32(&mut self) -> io::Result<u32> {
+        if self.0.len() < 4 {
+            return Err(invalid_data("truncated RPC reply"));
+        }
+        let (value, rest) = self.0.split_at(4);
+        self.0 = rest;
+        Ok(u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+    }
+    fn opaque(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        let padded = len + (4 - len % 4) % 4;
+        if self.0.len() < padded {
+            return Err(invalid_data("truncated RPC reply"));
+        }
+        let data = &self.0[..len];
+        self.0 = &self.0[padded..];
+        Ok(data)
+    }
+}
+
+/// Writes a single record using ONC RPC record marking
+///
+/// Reference: RFC 5531: 11 - Record Marking Standard
+fn write_record<W: Write>(w: &mut W, record: &[u8]) -> io::Result<()> {
+    let header = 0x8000_0000 | record.len() as u32;
+    let mut buf = Vec::with_capacity(record.len() + 4);
+    buf.extend_from_slice(&header.to_be_bytes());
+    buf.extend_from_slice(record);
+    w.write_all(&buf)?;
+    w.flush()
+}
+
+/// Reads a single record consisting of one or more fragments
+fn read_record<R: Read>(r: &mut R) -> io::Result<Vec<u8>> {
+    let mut record = Vec::new();
+    loop {
+        let mut header = [0; 4];
+        r.read_exact(&mut header)?;
+        let header = u32::from_be_bytes(header);
+        let start = record.len();
+        record.resize(start + (header & 0x7fff_ffff) as usize, 0);
+        r.read_exact(&mut record[start..])?;
+        if header & 0x8000_0000 != 0 {
+            return Ok(record);
+        }
+    }
+}
+
+/// ONC RPC client over a TCP stream
+///
+/// Reference: RFC 5531 - RPC: Remote Procedure Call Protocol Specification Version 2
+#[derive(Debug)]
+struct RpcClient {
+    stream: TcpStream,
+    program: u32,
+    version: u32,
+    xid: u32,
+}
+
+impl RpcClient {
+    fn call(&mut self, procedure: u32, args: XdrWriter) -> io::Result<Vec<u8>> {
+        self.xid = self.xid.wrapping_add(1);
+        let mut call = XdrWriter::default()
+            .u32(self.xid)
+            // CALL
+            .u32(0)
+            // RPC version
+            .u32(2)
+            .u32(self.program)
+            .u32(self.version)
+            .u32(procedure)
+            // AUTH_NONE credentials and verifier
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u32(0);
+        call.0.extend_from_slice(&args.0);
+        write_record(&mut self.stream, &call.0)?;
+        loop {
+            let reply = read_record(&mut self.stream)?;
+            let mut r = XdrReader(&reply);
+            // Replies to earlier, abandoned calls are skipped
+            if r.u32()? != self.xid {
+                continue;
+            }
+            if r.u32()? != 1 {
+                return Err(invalid_data("expected RPC reply"));
+            }
+            if r.u32()? != 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "RPC call denied",
+                ));
+            }
+            // Verifier flavor and body
+            r.u32()?;
+            r.opaque()?;
+            if r.u32()? != 0 {
+                return Err(io::Error::other("RPC call not accepted"));
+            }
+            return Ok(r.0.to_vec());
+        }
+    }
+}
+
+/// Asks the portmapper for the TCP port of the VXI-11 core channel
+fn core_channel_port(addr: SocketAddr, timeout: Duration) -> io::Result<u16> {
+    let stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut client = RpcClient {
+        stream,
+        program: PORTMAPPER,
+        version: PORTMAPPER_VERSION,
+        xid: 0,
+    };
+    let args = XdrWriter::default()
+        .u32(DEVICE_CORE)
+        .u32(DEVICE_CORE_VERSION)
+        .u32(IPPROTO_TCP)
+        .u32(0);
+    let reply = client.call(PORTMAPPER_GETPORT, args)?;
+    match u16::try_from(XdrReader(&reply).u32()?) {
+        Ok(port) if port != 0 => Ok(port),
+        _ => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "VXI-11 core channel is not registered",
+        )),
+    }
+}
+
+/// VXI-11 core channel connection to a device (e.g. `inst0`)
+///
+/// Reference: VXI-11 Revision 1.0: TCP/IP Instrument Protocol Specification
+#[derive(Debug)]
+pub struct Vxi11Transport {
+    rpc: RpcClient,
+    lid: u32,
+    max_recv_size: u32,
+    io_timeout: Duration,
+    lock_timeout: Duration,
+    term_char: Option<u8>,
+    closed: bool,
+}
+
+impl Vxi11Transport {
+    /// Device name used by most instruments
+    pub const DEFAULT_DEVICE: &'static str = "inst0";
+
+    /// Connects to a device, looking up the core channel port using the portmapper
+    pub fn connect(host: &str, device: &str, timeout: Duration) -> io::Result<Vxi11Transport> {
+        let mut last_err = None;
+        for addr in (host, PORTMAPPER_PORT).to_socket_addrs()? {
+            let result = core_channel_port(addr, timeout).and_then(|port| {
+                Vxi11Transport::connect_port(SocketAddr::new(addr.ip(), port), device, timeout)
+            });
+            match result {
+                Ok(transport) => return Ok(transport),
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "no addresses to connect to")
+        }))
+    }
+    /// Connects to a device using a known core channel address
+    pub fn connect_port(
+        addr: SocketAddr,
+        device: &str,
+        timeout: Duration,
+    ) -> io::Result<Vxi11Transport> {
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_nodelay(true)?;
+        let mut transport = Vxi11Transport {
+            rpc: RpcClient {
+                stream,
+                program: DEVICE_CORE,
+                version: DEVICE_CORE_VERSION,
+                xid: 0,
+            },
+            lid: 0,
+            max_recv_size: 0,
+            io_timeout: timeout,
+            lock_timeout: timeout,
+            term_char: None,
+            closed: false,
+        };
+        transport.set_io_timeout(timeout)?;
+        let args = XdrWriter::default()
+            // Client ID
+            .u32(0)
+            // Lock device
+            .bool(false)
+            .u32(millis(transport.lock_timeout))
+            .opaque(device.as_bytes());
+        let reply = transport.rpc.call(CREATE_LINK, args)?;
+        let mut r = XdrReader(&reply);
+        check_error(r.u32()?)?;
+        transport.lid = r.u32()?;
+        // Abort port
+        r.u32()?;
+        transport.max_recv_size = r.u32()?;
+        Ok(transport)
+    }
+    /// Sets the I/O timeout sent to the device
+    ///
+    /// The socket timeout is set slightly longer so the device has a chance to report a timeout.
+    pub fn set_io_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.io_timeout = timeout;
+        let socket_timeout = Some(timeout + Duration::from_secs(1));
+        self.rpc.stream.set_read_timeout(socket_timeout)?;
+        self.rpc.stream.set_write_timeout(socket_timeout)
+    }
+    /// Sets the time to wait for a lock held by another link
+    pub fn set_lock_timeout(&mut self, timeout: Duration) {
+        self.lock_timeout = timeout;
+    }
+    /// Sets the character that terminates reads in addition to END
+    pub fn set_term_char(&mut self, term_char: Option<u8>) {
+        self.term_char = term_char;
+    }
+    /// Returns the largest write the device accepts in a single call
+    pub fn max_recv_size(&self) -> u32 {
+        self.max_recv_size
+    }
+    /// Writes data, sending END with the last byte if `end` is set
+    ///
+    /// Data larger than the maximum receive size is split into several calls.
+    pub fn device_write(&mut self, data: &[u8], end: bool) -> io::Result<()> {
+        let chunk_size = match self.max_recv_size {
+            0 => data.len().max(1),
+            size => size as usize,
+        };
+        let mut chunks = data.chunks(chunk_size).peekable();
+        if data.is_empty() {
+            return self.write_chunk(&[], end);
+        }
+        while let Some(chunk) = chunks.next() {
+            self.write_chunk(chunk, end && chunks.peek().is_none())?;
+        }
+        Ok(())
+    }
+    /// Reads data until END, the termination character, or the request size is reached
+    ///
+    /// Returns the data and whether the response message is complete.
+    pub fn device_read(&mut self, request_size: u32) -> io::Result<(Vec<u8>, bool)> {
+        let mut flags = FLAG_WAITLOCK;
+        if self.term_char.is_some() {
+            flags |= FLAG_TERMCHRSET;
+        }
+        let args = XdrWriter::default()
+            .u32(self.lid)
+            .u32(request_size)
+            .u32(millis(self.io_timeout))
+            .u32(millis(self.lock_timeout))
+            .u32(flags)
+            .u32(u32::from(self.term_char.unwrap_or(0)));
+        let reply = self.rpc.call(DEVICE_READ, args)?;
+        let mut r = XdrReader(&reply);
+        check_error(r.u32()?)?;
+        let reason = r.u32()?;
+        let data = r.opaque()?.to_vec();
+        Ok((data, reason & (REASON_END | REASON_CHR) != 0))
+    }
+    /// Reads the status byte
+    pub fn read_stb(&mut self) -> io::Result<StatusByte> {
+        let reply = self.rpc.call(DEVICE_READSTB, self.generic_params())?;
+        let mut r = XdrReader(&reply);
+        check_error(r.u32()?)?;
+        Ok(StatusByte(r.u32()? as u8))
+    }
+    /// Sends a group execute trigger
+    pub fn trigger(&mut self) -> io::Result<()> {
+        self.generic_call(DEVICE_TRIGGER)
+    }
+    /// Sends a device clear
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.generic_call(DEVICE_CLEAR)
+    }
+    /// Places the device in the remote state
+    pub fn remote(&mut self) -> io::Result<()> {
+        self.generic_call(DEVICE_REMOTE)
+    }
+    /// Places the device in the local state
+    pub fn local(&mut self) -> io::Result<()> {
+        self.generic_call(DEVICE_LOCAL)
+    }
+    /// Acquires an exclusive lock, waiting up to the lock timeout
+    pub fn lock(&mut self) -> io::Result<()> {
+        let args = XdrWriter::default()
+            .u32(self.lid)
+            .u32(FLAG_WAITLOCK)
+            .u32(millis(self.lock_timeout));
+        let reply = self.rpc.call(DEVICE_LOCK, args)?;
+        check_error(XdrReader(&reply).u32()?)
+    }
+    /// Releases the lock held by this link
+    pub fn unlock(&mut self) -> io::Result<()> {
+        let reply = self
+            .rpc
+            .call(DEVICE_UNLOCK, XdrWriter::default().u32(self.lid))?;
+        check_error(XdrReader(&reply).u32()?)
+    }
+    /// Destroys the link and closes the connection
+    pub fn close(mut self) -> io::Result<()> {
+        self.closed = true;
+        self.destroy_link()
+    }
+    fn destroy_link(&mut self) -> io::Result<()> {
+        let reply = self
+            .rpc
+            .call(DESTROY_LINK, XdrWriter::default().u32(self.lid))?;
+        check_error(XdrReader(&reply).u32()?)
+    }
+    fn write_chunk(&mut self, data: &[u8], end: bool) -> io::Result<()> {
+        let mut flags = FLAG_WAITLOCK;
+        if end {
+            flags |= FLAG_END;
+        }
+        let args = XdrWriter::default()
+            .u32(self.lid)
+            .u32(millis(self.io_timeout))
+            .u32(millis(self.lock_timeout))
+            .u32(flags)
+            .opaque(data);
+        let reply = self.rpc.call(DEVICE_WRITE, args)?;
+        let mut r = XdrReader(&reply);
+        check_error(r.u32()?)?;
+        if r.u32()? as usize != data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "device accepted only part of the data",
+            ));
+        }
+        Ok(())
+    }
+    fn generic_params(&self) -> XdrWriter {
+        XdrWriter::default()
+            .u32(self.lid)
+            .u32(FLAG_WAITLOCK)
+            .u32(millis(self.lock_timeout))
+            .u32(millis(self.io_timeout))
+    }
+    fn generic_call(&mut self, procedure: u32) -> io::Result<()> {
+        let reply = self.rpc.call(procedure, self.generic_params())?;
+        check_error(XdrReader(&reply).u32()?)
+    }
+}
+
+impl Drop for Vxi11Transport {
+    fn drop(&mut self) {
+        if !self.closed {
+            let _ = self.destroy_link();
+        }
+    }
+}
+
+impl Transport for Vxi11Transport {
+    fn write_message(&mut self, message: &[u8]) -> io::Result<()> {
+        self.device_write(message, true)
+    }
+    fn read_message(&mut self) -> io::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        loop {
+            let (data, complete) = self.device_read(0x10_0000)?;
+            message.extend_from_slice(&data);
+            if complete {
+                return Ok(message);
+            }
+        }
+    }
+    fn terminator(&self) -> Terminator {
+        Terminator::NewlineEnd
+    }
+}
+
+fn millis(duration: Duration) -> u32 {
+    u32::try_from(duration.as_millis()).unwrap_or(u32::MAX)
+}
+
+fn check_error(code: u32) -> io::Result<()> {
+    match code {
+        0 => Ok(()),
+        code => Err(Vxi11Error(code).into()),
+    }
+}
+
+#[cfg(test)]
+/// Minimal stand-in for an instrument that serves the portmapper and core channel
+fn serve(mut stream: TcpStream, mut handler: impl FnMut(u32, XdrReader) -> XdrWriter) {
+    while let Ok(call) = read_record(&mut stream) {
+        let mut r = XdrReader(&call);
+        let xid = r.u32().unwrap();
+        for _ in 0..3 {
+            r.u32().unwrap();
+        }
+        r.u32().unwrap();
+        let procedure = r.u32().unwrap();
+        r.u32().unwrap();
+        r.opaque().unwrap();
+        r.u32().unwrap();
+        r.opaque().unwrap();
+        let results = handler(procedure, r);
+        let mut reply = XdrWriter::default()
+            .u32(xid)
+            .u32(1)
+            .u32(0)
+            .u32(0)
+            .u32(0)
+            .u32(0);
+        reply.0.extend_from_slice(&results.0);
+        write_record(&mut stream, &reply.0).unwrap();
+    }
+}
+
+#[cfg(test)]
+fn spawn(
+    handler: impl FnMut(u32, XdrReader) -> XdrWriter + Send + 'static,
+) -> (SocketAddr, std::thread::JoinHandle<()>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handle = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        serve(stream, handler);
+    });
+    (addr, handle)
+}
+
+#[cfg(test)]
+fn instrument() -> (SocketAddr, std::thread::JoinHandle<()>) {
+    let mut written = Vec::new();
+    let mut response = Vec::new();
+    spawn(move |procedure, mut r| match procedure {
+        CREATE_LINK => {
+            r.u32().unwrap();
+            r.u32().unwrap();
+            r.u32().unwrap();
+            assert_eq!(r.opaque().unwrap(), b"inst0");
+            // Small receive size to exercise splitting writes
+            XdrWriter::default().u32(0).u32(7).u32(0).u32(8)
+        }
+        DEVICE_WRITE => {
+            r.u32().unwrap();
+            r.u32().unwrap();
+            r.u32().unwrap();
+            let flags = r.u32().unwrap();
+            let data = r.opaque().unwrap();
+            written.extend_from_slice(data);
+            if flags & FLAG_END != 0 {
+                assert_eq!(written, b"*RST;*IDN?\n");
+                written.clear();
+                response = b"ACME,X1,1234,1.0\n".to_vec();
+            }
+            XdrWriter::default().u32(0).u32(data.len() as u32)
+        }
+        DEVICE_READ => {
+            // Respond in two parts to exercise END handling
+            let len = response.len().min(10);
+            let data: Vec<u8> = response.drain(..len).collect();
+            let reason = if response.is_empty() { REASON_END } else { 0 };
+            XdrWriter::default().u32(0).u32(reason).opaque(&data)
+        }
+        DEVICE_READSTB => XdrWriter::default().u32(0).u32(0x50),
+        DEVICE_LOCK => XdrWriter::default().u32(11),
+        DEVICE_TRIGGER | DEVICE_CLEAR | DEVICE_UNLOCK | DESTROY_LINK => {
+            assert_eq!(r.u32().unwrap(), 7);
+            XdrWriter::default().u32(0)
+        }
+        _ => XdrWriter::default().u32(8),
+    })
+}
+
+#[test]
+fn test_vxi11_query() {
+    use crate::common::{IdnQuery, Rst};
+    let (addr, handle) = instrument();
+    let mut transport =
+        Vxi11Transport::connect_port(addr, "inst0", Duration::from_secs(5)).unwrap();
+    assert_eq!(transport.max_recv_size(), 8);
+    let mut msg = transport.message();
+    msg.command(Rst).unwrap();
+    msg.command(IdnQuery).unwrap();
+    transport.write_message(&msg.finish().unwrap()).unwrap();
+    let response = transport.read_message().unwrap();
+    assert_eq!(response, b"ACME,X1,1234,1.0\n");
+    transport.close().unwrap();
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_vxi11_device_operations() {
+    let (addr, handle) = instrument();
+    let mut transport =
+        Vxi11Transport::connect_port(addr, "inst0", Duration::from_secs(5)).unwrap();
+    assert_eq!(
+        transport.read_stb().unwrap(),
+        StatusByte::MAV | StatusByte::RQS
+    );
+    transport.trigger().unwrap();
+    transport.clear().unwrap();
+    let err = transport.lock().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+    assert_eq!(
+        err.get_ref().unwrap().downcast_ref::<Vxi11Error>(),
+        Some(&Vxi11Error(11))
+    );
+    transport.unlock().unwrap();
+    assert_eq!(transport.remote().unwrap_err().kind(), io::ErrorKind::Other);
+    // Dropping the transport destroys the link
+    drop(transport);
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_portmapper_getport() {
+    let (addr, handle) = spawn(|procedure, mut r| {
+        assert_eq!(procedure, PORTMAPPER_GETPORT);
+        assert_eq!(r.u32().unwrap(), DEVICE_CORE);
+        assert_eq!(r.u32().unwrap(), DEVICE_CORE_VERSION);
+        assert_eq!(r.u32().unwrap(), IPPROTO_TCP);
+        XdrWriter::default().u32(1024)
+    });
+    assert_eq!(
+        core_channel_port(addr, Duration::from_secs(5)).unwrap(),
+        1024
+    );
+    handle.join().unwrap();
+}
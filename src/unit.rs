@@ -0,0 +1,254 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::io::Write;
+
+use crate::{EncodeError, Parameter};
+
+/// SI multiplier used in suffix program data
+///
+/// Reference: SCPI 1999.0: 7.4.3 - Suffix Multipliers
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum Multiplier {
+    Exa,
+    Peta,
+    Tera,
+    Giga,
+    Mega,
+    Kilo,
+    #[default]
+    None,
+    Milli,
+    Micro,
+    Nano,
+    Pico,
+    Femto,
+    Atto,
+}
+
+impl Multiplier {
+    /// Returns the suffix mnemonic of the multiplier
+    ///
+    /// `M` means milli, so mega is encoded as `MA`.
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            Multiplier::Exa => "EX",
+            Multiplier::Peta => "PE",
+            Multiplier::Tera => "T",
+            Multiplier::Giga => "G",
+            Multiplier::Mega => "MA",
+            Multiplier::Kilo => "K",
+            Multiplier::None => "",
+            Multiplier::Milli => "M",
+            Multiplier::Micro => "U",
+            Multiplier::Nano => "N",
+            Multiplier::Pico => "P",
+            Multiplier::Femto => "F",
+            Multiplier::Atto => "A",
+        }
+    }
+    /// Returns the power of ten the multiplier represents
+    pub fn exponent(self) -> i32 {
+        match self {
+            Multiplier::Exa => 18,
+            Multiplier::Peta => 15,
+            Multiplier::Tera => 12,
+            Multiplier::Giga => 9,
+            Multiplier::Mega => 6,
+            Multiplier::Kilo => 3,
+            Multiplier::None => 0,
+            Multiplier::Milli => -3,
+            Multiplier::Micro => -6,
+            Multiplier::Nano => -9,
+            Multiplier::Pico => -12,
+            Multiplier::Femto => -15,
+            Multiplier::Atto => -18,
+        }
+    }
+}
+
+/// Encodes a value followed by a suffix consisting of a multiplier and a unit
+///
+/// Reference: SCPI 1999.0: 7.4 - Suffix Program Data
+fn encode_suffixed<W: Write>(
+    value: f64,
+    multiplier: Multiplier,
+    unit: &str,
+    w: &mut W,
+) -> Result<(), EncodeError> {
+    match (multiplier, unit) {
+        // SCPI 1999.0: 7.4.3 - MHZ and MOHM are special cases that mean mega
+        (Multiplier::Mega, "HZ") => encode_with(value, "MHZ", w),
+        (Multiplier::Mega, "OHM") => encode_with(value, "MOHM", w),
+        // Milli can't be expressed, so the value is scaled to the base unit.
+        // MA would be read as mega instead of milliamps
+        (Multiplier::Milli, "HZ") | (Multiplier::Milli, "OHM") | (Multiplier::Milli, "A") => {
+            encode_with(value * 1E-3, unit, w)
+        }
+        (multiplier, unit) => {
+            let mut suffix = String::from(multiplier.mnemonic());
+            suffix.push_str(unit);
+            encode_with(value, &suffix, w)
+        }
+    }
+}
+
+fn encode_with<W: Write>(value: f64, suffix: &str, w: &mut W) -> Result<(), EncodeError> {
+    value.encode(w)?;
+    if value.is_finite() {
+        w.write_all(b" ")?;
+        w.write_all(suffix.as_bytes())?;
+    }
+    Ok(())
+}
+
+macro_rules! scaled_units {
+    ($($(#[$attr:meta])* $ty:ident => $unit:expr),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            #[derive(Copy, Clone, Debug, PartialEq)]
+            pub struct $ty {
+                pub value: f64,
+                pub multiplier: Multiplier,
+            }
+
+            impl $ty {
+                pub fn new(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::None)
+                }
+                pub fn with_multiplier(value: f64, multiplier: Multiplier) -> $ty {
+                    $ty { value, multiplier }
+                }
+                pub fn giga(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Giga)
+                }
+                pub fn mega(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Mega)
+                }
+                pub fn kilo(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Kilo)
+                }
+                pub fn milli(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Milli)
+                }
+                pub fn micro(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Micro)
+                }
+                pub fn nano(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Nano)
+                }
+                pub fn pico(value: f64) -> $ty {
+                    $ty::with_multiplier(value, Multiplier::Pico)
+                }
+                /// Returns the value in the base unit without a multiplier
+                pub fn base_value(self) -> f64 {
+                    self.value * 10f64.powi(self.multiplier.exponent())
+                }
+            }
+
+            impl Parameter for $ty {
+                fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    encode_suffixed(self.value, self.multiplier, $unit, w)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! plain_units {
+    ($($(#[$attr:meta])* $ty:ident => $unit:expr),* $(,)?) => {
+        $(
+            $(#[$attr])*
+            #[repr(transparent)]
+            #[derive(Copy, Clone, Debug, PartialEq)]
+            pub struct $ty(pub f64);
+
+            impl Parameter for $ty {
+                fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+                    encode_with(self.0, $unit, w)
+                }
+            }
+        )*
+    };
+}
+
+scaled_units! {
+    /// Voltage (`V`)
+    Volts => "V",
+    /// Current (`A`)
+    Amps => "A",
+    /// Frequency (`HZ`)
+    Hertz => "HZ",
+    /// Time (`S`)
+    Seconds => "S",
+    /// Resistance (`OHM`)
+    Ohms => "OHM",
+    /// Power (`W`)
+    Watts => "W",
+    /// Capacitance (`F`)
+    Farads => "F",
+    /// Inductance (`H`)
+    Henries => "H",
+}
+
+plain_units! {
+    /// Relative level in decibels (`DB`)
+    Decibels => "DB",
+    /// Power level relative to 1 mW (`DBM`)
+    DecibelMilliwatts => "DBM",
+    /// Power level relative to 1 W (`DBW`)
+    DecibelWatts => "DBW",
+    /// Voltage level relative to 1 uV (`DBUV`)
+    DecibelMicrovolts => "DBUV",
+    /// Temperature in degrees Celsius (`CEL`)
+    Celsius => "CEL",
+}
+
+#[cfg(test)]
+fn encode<P: Parameter>(param: P) -> Result<String, EncodeError> {
+    let mut buf = Vec::new();
+    param.encode(&mut buf)?;
+    Ok(String::from_utf8(buf).unwrap())
+}
+
+#[test]
+fn test_scaled_units() {
+    assert_eq!(encode(Volts::milli(100.0)).unwrap(), "1E2 MV");
+    assert_eq!(encode(Volts::new(1.5)).unwrap(), "1.5E0 V");
+    assert_eq!(encode(Amps::mega(1.0)).unwrap(), "1E0 MAA");
+    assert_eq!(encode(Hertz::kilo(2.5)).unwrap(), "2.5E0 KHZ");
+    assert_eq!(encode(Seconds::micro(10.0)).unwrap(), "1E1 US");
+    assert_eq!(
+        encode(Farads::with_multiplier(4.7, Multiplier::Femto)).unwrap(),
+        "4.7E0 FF"
+    );
+    assert_eq!(Volts::milli(100.0).base_value(), 0.1);
+}
+
+#[test]
+fn test_mega_special_cases() {
+    assert_eq!(encode(Hertz::mega(10.0)).unwrap(), "1E1 MHZ");
+    assert_eq!(encode(Ohms::mega(2.0)).unwrap(), "2E0 MOHM");
+    assert_eq!(encode(Hertz::milli(500.0)).unwrap(), "5E-1 HZ");
+    assert_eq!(encode(Ohms::milli(1.0)).unwrap(), "1E-3 OHM");
+    assert_eq!(encode(Amps::milli(100.0)).unwrap(), "1E-1 A");
+    assert_eq!(encode(Watts::mega(1.0)).unwrap(), "1E0 MAW");
+}
+
+#[test]
+fn test_plain_units() {
+    assert_eq!(encode(DecibelMilliwatts(-10.0)).unwrap(), "-1E1 DBM");
+    assert_eq!(encode(Decibels(3.0)).unwrap(), "3E0 DB");
+    assert_eq!(encode(Celsius(f64::NAN)).unwrap(), "NAN");
+}
+
+#[test]
+fn test_units_out_of_range() {
+    let mut buf = Vec::new();
+    assert!(matches!(
+        Volts::kilo(1E300).encode(&mut buf),
+        Err(EncodeError::OutOfRange(_))
+    ));
+    assert!(buf.is_empty());
+}
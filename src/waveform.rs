@@ -0,0 +1,281 @@
+// SPDX-FileCopyrightText: 2020-2021 Joonas Javanainen <joonas.javanainen@gmail.com>
+//
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+use std::{borrow::Cow, io::Write, mem, slice};
+
+use crate::{
+    response::{FromResponse, Parser},
+    Block, DecodeError, Discrete, EncodeError, Parameter,
+};
+
+/// Byte order of binary block data
+///
+/// Reference: SCPI 1999.0: 9.1 - FORMat:BORDer
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ByteOrder {
+    /// Big-endian byte order (`NORMal`)
+    #[default]
+    Normal,
+    /// Little-endian byte order (`SWAPped`)
+    Swapped,
+}
+
+impl ByteOrder {
+    /// Returns the byte order of the host
+    pub fn native() -> ByteOrder {
+        if cfg!(target_endian = "big") {
+            ByteOrder::Normal
+        } else {
+            ByteOrder::Swapped
+        }
+    }
+}
+
+impl Parameter for ByteOrder {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        w.write_all(match self {
+            ByteOrder::Normal => b"NORM",
+            ByteOrder::Swapped => b"SWAP",
+        })?;
+        Ok(())
+    }
+}
+
+impl<'a> FromResponse<'a> for ByteOrder {
+    fn from_response(p: &mut Parser<'a>) -> Result<Self, DecodeError> {
+        let start = p.position();
+        let text = p.character()?;
+        if text.eq_ignore_ascii_case("NORM") || text.eq_ignore_ascii_case("NORMAL") {
+            Ok(ByteOrder::Normal)
+        } else if text.eq_ignore_ascii_case("SWAP") || text.eq_ignore_ascii_case("SWAPPED") {
+            Ok(ByteOrder::Swapped)
+        } else {
+            Err(DecodeError::Syntax {
+                expected: "NORMal or SWAPped",
+                position: start,
+            })
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Numeric type that can be transferred as binary block data
+///
+/// The trait is sealed, because the zero-copy conversions rely on every bit
+/// pattern being a valid value of the type.
+pub trait Sample: Copy + private::Sealed {
+    /// Returns the `FORMat:DATA` parameters matching the type (e.g. `REAL,32`)
+    ///
+    /// Reference: SCPI 1999.0: 9.2 - FORMat[:DATA]
+    fn data_format() -> (Discrete<'static>, u8);
+    #[doc(hidden)]
+    fn write_bytes(self, order: ByteOrder, buf: &mut [u8]);
+    #[doc(hidden)]
+    fn read_bytes(order: ByteOrder, buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_sample {
+    ($($ty:ident => $format:expr),*) => {
+        $(
+            impl private::Sealed for $ty {}
+
+            impl Sample for $ty {
+                fn data_format() -> (Discrete<'static>, u8) {
+                    (Discrete($format), (mem::size_of::<$ty>() * 8) as u8)
+                }
+                fn write_bytes(self, order: ByteOrder, buf: &mut [u8]) {
+                    buf.copy_from_slice(&match order {
+                        ByteOrder::Normal => self.to_be_bytes(),
+                        ByteOrder::Swapped => self.to_le_bytes(),
+                    });
+                }
+                fn read_bytes(order: ByteOrder, buf: &[u8]) -> Self {
+                    let mut bytes = [0; mem::size_of::<$ty>()];
+                    bytes.copy_from_slice(buf);
+                    match order {
+                        ByteOrder::Normal => $ty::from_be_bytes(bytes),
+                        ByteOrder::Swapped => $ty::from_le_bytes(bytes),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_sample!(
+    f32 => "REAL",
+    f64 => "REAL",
+    i8 => "INT",
+    i16 => "INT",
+    i32 => "INT",
+    u8 => "UINT",
+    u16 => "UINT",
+    u32 => "UINT"
+);
+
+/// Slice of samples encoded as a definite length arbitrary block
+///
+/// Reference: SCPI 1999.0: 9.2 - FORMat[:DATA]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Samples<'a, T> {
+    pub data: &'a [T],
+    pub order: ByteOrder,
+}
+
+impl<'a, T> Samples<'a, T> {
+    pub fn new(data: &'a [T], order: ByteOrder) -> Samples<'a, T> {
+        Samples { data, order }
+    }
+}
+
+impl<'a, T: Sample> Parameter for Samples<'a, T> {
+    fn encode<W: Write>(self, w: &mut W) -> Result<(), EncodeError> {
+        let size = mem::size_of::<T>();
+        if self.order == ByteOrder::native() || size == 1 {
+            // Safety: samples are plain numeric types without padding
+            let bytes = unsafe {
+                slice::from_raw_parts(self.data.as_ptr() as *const u8, mem::size_of_val(self.data))
+            };
+            Block(bytes).encode(w)
+        } else {
+            let mut bytes = vec![0; mem::size_of_val(self.data)];
+            for (sample, buf) in self.data.iter().zip(bytes.chunks_exact_mut(size)) {
+                sample.write_bytes(self.order, buf);
+            }
+            Block(&bytes).encode(w)
+        }
+    }
+}
+
+/// Converts block data to samples
+///
+/// The data is borrowed without copying if it's suitably aligned and uses the
+/// native byte order.
+pub fn decode_samples<T: Sample>(
+    data: &[u8],
+    order: ByteOrder,
+) -> Result<Cow<'_, [T]>, DecodeError> {
+    let size = mem::size_of::<T>();
+    if !data.len().is_multiple_of(size) {
+        return Err(DecodeError::BlockLength {
+            len: data.len(),
+            sample_size: size,
+        });
+    }
+    // Byte order is irrelevant for single byte samples
+    if order == ByteOrder::native() || size == 1 {
+        // Safety: every bit pattern is a valid sample
+        let (prefix, samples, _) = unsafe { data.align_to::<T>() };
+        if prefix.is_empty() {
+            return Ok(Cow::Borrowed(samples));
+        }
+    }
+    Ok(Cow::Owned(
+        data.chunks_exact(size)
+            .map(|buf| T::read_bytes(order, buf))
+            .collect(),
+    ))
+}
+
+/// Decodes arbitrary block response data as samples
+pub fn parse_samples<'a, T: Sample>(
+    p: &mut Parser<'a>,
+    order: ByteOrder,
+) -> Result<Cow<'a, [T]>, DecodeError> {
+    let data = p.block()?;
+    decode_samples(data, order)
+}
+
+#[test]
+fn test_samples_parameter() {
+    let mut buf = Vec::new();
+    Samples::new(&[1i16, -2], ByteOrder::Normal)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#14\x00\x01\xff\xfe");
+    buf.clear();
+    Samples::new(&[1i16, -2], ByteOrder::Swapped)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#14\x01\x00\xfe\xff");
+    buf.clear();
+    Samples::new(&[1.0f32], ByteOrder::Normal)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"#14\x3f\x80\x00\x00");
+}
+
+#[test]
+fn test_decode_samples() {
+    let data = [0x3f, 0x80, 0x00, 0x00, 0xc0, 0x00, 0x00, 0x00];
+    assert_eq!(
+        decode_samples::<f32>(&data, ByteOrder::Normal).unwrap()[..],
+        [1.0, -2.0]
+    );
+    assert_eq!(
+        decode_samples::<u16>(&data, ByteOrder::Swapped).unwrap()[..],
+        [0x803f, 0x0000, 0x00c0, 0x0000]
+    );
+    assert_eq!(
+        decode_samples::<u32>(&data[..6], ByteOrder::Normal),
+        Err(DecodeError::BlockLength {
+            len: 6,
+            sample_size: 4
+        })
+    );
+}
+
+#[test]
+fn test_decode_samples_borrowed() {
+    let samples = [1u16, 2, 3];
+    let mut buf = Vec::new();
+    Samples::new(&samples, ByteOrder::native())
+        .encode(&mut buf)
+        .unwrap();
+    let data = crate::response::parse::<Block>(&buf).unwrap().0;
+    let decoded = decode_samples::<u16>(data, ByteOrder::native()).unwrap();
+    assert_eq!(decoded[..], samples);
+    // Aligned native order data is decoded without copying
+    let mut storage = [0u8; 7];
+    let offset = storage.as_ptr().align_offset(mem::align_of::<u16>());
+    let aligned = &mut storage[offset..offset + 6];
+    for (bytes, sample) in aligned.chunks_exact_mut(2).zip(&samples) {
+        bytes.copy_from_slice(&sample.to_ne_bytes());
+    }
+    let decoded = decode_samples::<u16>(aligned, ByteOrder::native()).unwrap();
+    assert!(matches!(decoded, Cow::Borrowed(borrowed) if borrowed == samples));
+    let data = &[0u8, 1, 2, 3];
+    // Single bytes are always aligned
+    assert!(matches!(
+        decode_samples::<u8>(data, ByteOrder::Normal).unwrap(),
+        Cow::Borrowed(_)
+    ));
+}
+
+#[test]
+fn test_parse_samples() {
+    let mut p = Parser::new(b"#14\x00\x01\xff\xfe\n");
+    assert_eq!(
+        parse_samples::<i16>(&mut p, ByteOrder::Normal).unwrap()[..],
+        [1, -2]
+    );
+    assert_eq!(p.finish(), Ok(()));
+}
+
+#[test]
+fn test_data_format() {
+    let mut buf = Vec::new();
+    (f32::data_format(), ByteOrder::Swapped)
+        .encode(&mut buf)
+        .unwrap();
+    assert_eq!(buf, b"REAL,32,SWAP");
+    assert_eq!(i8::data_format(), (Discrete("INT"), 8));
+    assert_eq!(
+        crate::response::parse::<ByteOrder>(b"NORM\n"),
+        Ok(ByteOrder::Normal)
+    );
+}